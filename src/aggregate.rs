@@ -0,0 +1,215 @@
+use crate::*;
+
+use std::collections::HashSet;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AggregateKind {
+    Sum,
+    Count,
+    CountDistinct,
+    Avg,
+    Min,
+    Max,
+}
+
+impl std::fmt::Display for AggregateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind_str = match self {
+            AggregateKind::Sum => "SUM",
+            AggregateKind::Count | AggregateKind::CountDistinct => "COUNT",
+            AggregateKind::Avg => "AVG",
+            AggregateKind::Min => "MIN",
+            AggregateKind::Max => "MAX",
+        };
+        write!(f, "{}", kind_str)
+    }
+}
+
+// What the aggregate gets computed over for every record
+#[derive(Debug)]
+pub enum AggregateInput {
+    // COUNT(*), every record counts regardless of its values
+    Star,
+    // a plain attribute, which unlike a `Function` can also be a STRING
+    Attribute(usize, Type),
+    Function(Function),
+}
+
+impl AggregateInput {
+    pub fn input_type(&self) -> Type {
+        match self {
+            AggregateInput::Star => Type::Integer,
+            AggregateInput::Attribute(_, type_) => *type_,
+            AggregateInput::Function(function) => function.output_type(),
+        }
+    }
+}
+
+// The running state of an aggregate over the records of the current group
+#[derive(Debug, Default)]
+struct Accumulator {
+    // number of non-null values seen
+    count: i64,
+    // the running sum, minimum or maximum, `None` until a non-null value is seen. For AVG the sum
+    // is always kept as a float.
+    value: Option<ProjectedData>,
+    // the distinct values seen, only used for COUNT(DISTINCT ...)
+    seen: HashSet<ProjectedData>,
+}
+
+#[derive(Debug)]
+pub struct AggregateFunction {
+    kind: AggregateKind,
+    input: AggregateInput,
+    accumulator: Accumulator,
+}
+
+impl AggregateFunction {
+    // Only counting, MIN and MAX work on strings, so the other aggregates need a numeric input
+    pub fn new(kind: AggregateKind, input: AggregateInput) -> anyhow::Result<Self> {
+        let numeric_only = matches!(kind, AggregateKind::Sum | AggregateKind::Avg);
+        if numeric_only && !matches!(input.input_type(), Type::Integer | Type::Float) {
+            anyhow::bail!(
+                "{} needs a numeric argument, got {}",
+                kind,
+                input.input_type()
+            );
+        }
+
+        Ok(AggregateFunction {
+            kind,
+            input,
+            accumulator: Accumulator::default(),
+        })
+    }
+
+    pub fn get_kind(&self) -> AggregateKind {
+        self.kind
+    }
+
+    pub fn output_type(&self) -> Type {
+        match self.kind {
+            AggregateKind::Count | AggregateKind::CountDistinct => Type::Integer,
+            AggregateKind::Avg => Type::Float,
+            AggregateKind::Sum | AggregateKind::Min | AggregateKind::Max => self.input.input_type(),
+        }
+    }
+
+    // Starts a new group
+    pub fn reset(&mut self) {
+        self.accumulator = Accumulator::default();
+    }
+
+    // Fails if an integer SUM overflows
+    pub fn accumulate(&mut self, record: &Record) -> anyhow::Result<()> {
+        let value = match &self.input {
+            AggregateInput::Star => {
+                self.accumulator.count += 1;
+                return Ok(());
+            }
+            AggregateInput::Attribute(att, _) => record.get_column(*att).unwrap(),
+            AggregateInput::Function(function) => function.eval(record),
+        };
+
+        // nulls (from outer joins) are ignored, like in SQL
+        if value == MappedAttrData::Null {
+            return Ok(());
+        }
+
+        let acc = &mut self.accumulator;
+        acc.count += 1;
+
+        match self.kind {
+            AggregateKind::Count => (),
+            AggregateKind::CountDistinct => {
+                acc.seen.insert(value.into());
+            }
+            AggregateKind::Sum => {
+                acc.value = Some(match (acc.value.take(), value) {
+                    (None, value) => value.into(),
+                    (Some(ProjectedData::Integer(sum)), MappedAttrData::Integer(val)) => {
+                        match sum.checked_add(val) {
+                            Some(sum) => ProjectedData::Integer(sum),
+                            None => anyhow::bail!("integer overflow in {}", self.kind),
+                        }
+                    }
+                    (Some(ProjectedData::Float(sum)), MappedAttrData::Float(val)) => {
+                        ProjectedData::Float(sum + val)
+                    }
+                    (_, value) => return Err(self.type_mismatch(&value)),
+                });
+            }
+            AggregateKind::Avg => {
+                let val = match value {
+                    MappedAttrData::Integer(val) => val as f64,
+                    MappedAttrData::Float(val) => val,
+                    value => return Err(self.type_mismatch(&value)),
+                };
+
+                acc.value = Some(match acc.value.take() {
+                    Some(ProjectedData::Float(sum)) => ProjectedData::Float(sum + val),
+                    _ => ProjectedData::Float(val),
+                });
+            }
+            AggregateKind::Min | AggregateKind::Max => {
+                let wanted = if self.kind == AggregateKind::Min {
+                    std::cmp::Ordering::Less
+                } else {
+                    std::cmp::Ordering::Greater
+                };
+
+                let replace = match &acc.value {
+                    None => true,
+                    Some(current) => compare(&value, current) == Some(wanted),
+                };
+
+                if replace {
+                    acc.value = Some(value.into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // `new` makes sure the input has the right type, so this only happens if a function gives
+    // values of another type than it says it does
+    fn type_mismatch(&self, value: &MappedAttrData) -> anyhow::Error {
+        anyhow::anyhow!(
+            "{} of {} values got {:?}",
+            self.kind,
+            self.input.input_type(),
+            value
+        )
+    }
+
+    // Appends the value of the aggregate over the current group to `record`. Counts of a group
+    // without any non-null values are 0, every other aggregate is null.
+    pub fn push_result(&self, record: &mut Record) {
+        let acc = &self.accumulator;
+
+        match self.kind {
+            AggregateKind::Count => record.push_int(acc.count),
+            AggregateKind::CountDistinct => record.push_int(acc.seen.len() as i64),
+            AggregateKind::Avg => match acc.value {
+                Some(ProjectedData::Float(sum)) => record.push_flt(sum / acc.count as f64),
+                _ => record.push_null(),
+            },
+            AggregateKind::Sum | AggregateKind::Min | AggregateKind::Max => match &acc.value {
+                Some(ProjectedData::Integer(val)) => record.push_int(*val),
+                Some(ProjectedData::Float(val)) => record.push_flt(*val),
+                Some(ProjectedData::String(val)) => record.push_str(val),
+                Some(ProjectedData::Null) | None => record.push_null(),
+            },
+        }
+    }
+}
+
+fn compare(lhs: &MappedAttrData, rhs: &ProjectedData) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (MappedAttrData::Integer(lhs), ProjectedData::Integer(rhs)) => Some(lhs.cmp(rhs)),
+        (MappedAttrData::Float(lhs), ProjectedData::Float(rhs)) => lhs.partial_cmp(rhs),
+        (MappedAttrData::String(lhs), ProjectedData::String(rhs)) => Some((*lhs).cmp(rhs.as_str())),
+        _ => None,
+    }
+}
@@ -0,0 +1,763 @@
+use crate::comparison::*;
+use crate::db_file::*;
+use crate::record::*;
+use crate::types::*;
+use std::cmp::Ordering;
+use std::ops::Bound;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+
+// The first page of an index file says where the root is and which attributes the keys are
+// made of
+const META_PAGE: u64 = 0;
+
+// Every node of the tree is a page of its own. Slot 0 holds whether the node is a leaf and the
+// page of the next leaf, or of the first child for inner nodes. The entries follow in order,
+// each one is the key, the page and the slot of the record, and the child right of it for
+// inner nodes. Entries are sorted by the key and then by the record id, so every entry is
+// unique even if the keys aren't.
+const HEADER_SLOT: usize = 0;
+
+// A B+-tree on the records of another file, keyed by some of their attributes. It maps the
+// keys to the ids of the records, which may repeat keys. The nodes are pages of a data file of
+// type `FileType::Index`, so they go through the buffer pool like any other page.
+//
+// Deleting entries doesn't merge nodes that became small, the space gets reused by the next
+// entries that go there.
+#[derive(Debug)]
+pub struct BTreeIndex {
+    file: DBFile,
+    // the attributes of the indexed records the keys are made of
+    ordering: OrderMaker,
+    // compares entries by their keys, which hold the attributes of `ordering` in the same order
+    key_ordering: OrderMaker,
+    root: u64,
+    // bytes of a page a node can take up before it gets split
+    node_capacity: usize,
+}
+
+impl BTreeIndex {
+    pub fn create<P: AsRef<Path>>(file_path: P, ordering: OrderMaker) -> Result<Self> {
+        if ordering.atts.is_empty() {
+            return Err(anyhow!(
+                "An index needs at least one attribute for its keys"
+            ));
+        }
+
+        let mut file = DBFile::new();
+        file.create(file_path, FileType::Index)?;
+        file.new_page()?;
+        let root = file.new_page()?;
+
+        let mut index = BTreeIndex {
+            file,
+            key_ordering: key_ordering(&ordering),
+            ordering,
+            root,
+            node_capacity: MAX_RECORD_SIZE + SLOT_SIZE,
+        };
+        index.write_meta()?;
+        index.write_header(root, true, None)?;
+
+        Ok(index)
+    }
+
+    pub fn open<P: AsRef<Path>>(file_path: P) -> Result<Self> {
+        let mut file = DBFile::new();
+        file.open(file_path)?;
+        if file.get_file_type() != FileType::Index || file.get_num_pages() == 0 {
+            return Err(anyhow!("{} is not an index", file.get_file_name()));
+        }
+
+        let meta = file.with_page(META_PAGE, |page| page.get_record(0))?;
+        let meta = meta.ok_or_else(|| anyhow!("{} is not an index", file.get_file_name()))?;
+
+        let mut meta = meta.get_data().into_iter();
+        let mut int = || match meta.next() {
+            Some(MappedAttrData::Integer(val)) => Some(val),
+            _ => None,
+        };
+        let root = int();
+        let mut atts = Vec::new();
+        while let Some(att) = int() {
+            let type_ = match int() {
+                Some(0) => Type::Integer,
+                Some(1) => Type::Float,
+                Some(2) => Type::String,
+                _ => return Err(anyhow!("Corrupt index {}", file.get_file_name())),
+            };
+            atts.push((att as i32, type_));
+        }
+        let root = root.ok_or_else(|| anyhow!("Corrupt index {}", file.get_file_name()))?;
+
+        let ordering = OrderMaker { atts };
+        Ok(BTreeIndex {
+            file,
+            key_ordering: key_ordering(&ordering),
+            ordering,
+            root: root as u64,
+            node_capacity: MAX_RECORD_SIZE + SLOT_SIZE,
+        })
+    }
+
+    pub fn close(&mut self) -> Result<()> {
+        self.file.close()
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+
+    pub fn get_ordering(&self) -> &OrderMaker {
+        &self.ordering
+    }
+
+    pub fn get_file(&self) -> &DBFile {
+        &self.file
+    }
+
+    // Levels of the tree, a tree that's only a leaf has one
+    pub fn get_height(&self) -> Result<usize> {
+        let mut height = 1;
+        let mut page = self.root;
+        while let (_, Some(child)) = self.search(page, |_| false)? {
+            page = child;
+            height += 1;
+        }
+
+        Ok(height)
+    }
+
+    // The key of `record`, which the lookups take
+    pub fn get_key(&self, record: &Record) -> Result<Record> {
+        let key = self
+            .ordering
+            .atts
+            .iter()
+            .map(|&(att, _)| record.get_column(att as usize))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow!("Record doesn't have the attributes the index is on"))?;
+
+        let key = Record::from(key);
+        self.check_key(&key)?;
+        Ok(key)
+    }
+
+    // Adds the record stored at `rid` to the index. Adding it again doesn't change anything.
+    pub fn insert(&mut self, record: &Record, rid: RecordId) -> Result<()> {
+        let entry = new_entry(&self.get_key(record)?, rid);
+        // a split has to leave room for another entry in both halves, even in inner nodes
+        let size = with_child(&entry, 0).to_binary().len() + SLOT_SIZE;
+        if size > self.node_capacity / 4 {
+            return Err(anyhow!("Key of {size} bytes is too big for the index"));
+        }
+
+        if let Some((separator, right)) = self.insert_into(self.root, &entry)? {
+            // the root got split, so the tree grows by a level
+            let root = self.file.new_page()?;
+            self.write_header(root, false, Some(self.root))?;
+            self.insert_entry(root, &with_child(&separator, right))?;
+            self.root = root;
+            self.write_meta()?;
+        }
+
+        Ok(())
+    }
+
+    // Removes the record stored at `rid` from the index, gives false if it wasn't in there
+    pub fn delete(&mut self, record: &Record, rid: RecordId) -> Result<bool> {
+        let entry = new_entry(&self.get_key(record)?, rid);
+        let (leaf, pos) = self.find_leaf(|other| self.compare(other, &entry).is_le())?;
+        if pos == HEADER_SLOT || !self.entry_is(leaf, pos, &entry)? {
+            return Ok(false);
+        }
+
+        self.file.modify_page(leaf, |page| page.remove_at(pos))
+    }
+
+    // Ids of the records with the given key
+    pub fn lookup(&self, key: &Record) -> Result<Vec<RecordId>> {
+        self.range(Bound::Included(key), Bound::Included(key))
+    }
+
+    // Ids of the records with keys between the bounds, ordered by their keys
+    pub fn range(&self, lower: Bound<&Record>, upper: Bound<&Record>) -> Result<Vec<RecordId>> {
+        for bound in [lower, upper] {
+            if let Bound::Included(key) | Bound::Excluded(key) = bound {
+                self.check_key(key)?;
+            }
+        }
+
+        let below_lower = |entry: &Record| match lower {
+            Bound::Included(lower) => self.key_ordering.run(entry, lower).is_lt(),
+            Bound::Excluded(lower) => self.key_ordering.run(entry, lower).is_le(),
+            Bound::Unbounded => false,
+        };
+        let below_upper = |entry: &Record| match upper {
+            Bound::Included(upper) => self.key_ordering.run(entry, upper).is_le(),
+            Bound::Excluded(upper) => self.key_ordering.run(entry, upper).is_lt(),
+            Bound::Unbounded => true,
+        };
+
+        let (mut leaf, pos) = self.find_leaf(below_lower)?;
+        let mut first = pos + 1;
+        let mut rids = Vec::new();
+        loop {
+            let (entries, next) = self.read_leaf(leaf, first)?;
+            for entry in &entries {
+                if !below_upper(entry) {
+                    return Ok(rids);
+                }
+                rids.push(self.entry_rid(entry)?);
+            }
+
+            match next {
+                Some(next) => leaf = next,
+                None => return Ok(rids),
+            }
+            first = HEADER_SLOT + 1;
+        }
+    }
+
+    fn compare(&self, left: &Record, right: &Record) -> Ordering {
+        let key_len = self.key_ordering.atts.len();
+        self.key_ordering.run(left, right).then_with(|| {
+            let rid = |entry: &Record| (int_at(entry, key_len), int_at(entry, key_len + 1));
+            rid(left).cmp(&rid(right))
+        })
+    }
+
+    // Makes sure comparing `key` with the keys in the index works out
+    fn check_key(&self, key: &Record) -> Result<()> {
+        let matches = key.len() == self.key_ordering.atts.len()
+            && key
+                .get_data()
+                .iter()
+                .zip(&self.key_ordering.atts)
+                .all(|(data, (_, type_))| {
+                    matches!(
+                        (data, type_),
+                        (MappedAttrData::Null, _)
+                            | (MappedAttrData::Integer(_), Type::Integer)
+                            | (MappedAttrData::Float(_), Type::Float)
+                            | (MappedAttrData::String(_), Type::String)
+                    )
+                });
+
+        if !matches {
+            return Err(anyhow!(
+                "Key {:?} doesn't match the attributes of the index",
+                key.get_data()
+            ));
+        }
+        Ok(())
+    }
+
+    fn entry_rid(&self, entry: &Record) -> Result<RecordId> {
+        let key_len = self.key_ordering.atts.len();
+        match (int_at(entry, key_len), int_at(entry, key_len + 1)) {
+            (Some(page), Some(slot)) => Ok(RecordId {
+                page: page as u64,
+                slot: slot as usize,
+            }),
+            _ => Err(anyhow!("Corrupt entry in {}", self.file.get_file_name())),
+        }
+    }
+
+    // Whether `entry` is the one in `slot` of the node on `page`
+    fn entry_is(&self, page: u64, slot: usize, entry: &Record) -> Result<bool> {
+        let other = self.file.with_page(page, |page| page.get_record(slot))?;
+        Ok(other.is_some_and(|other| self.compare(&other, entry).is_eq()))
+    }
+
+    // Looks for the last entry of the node on `page` that `goes_right` holds for, the entries
+    // it holds for have to come before the others. Gives its slot, or the header slot if there
+    // is no such entry, along with the child that goes with it if the node isn't a leaf.
+    fn search(
+        &self,
+        page: u64,
+        goes_right: impl Fn(&Record) -> bool,
+    ) -> Result<(usize, Option<u64>)> {
+        let key_len = self.key_ordering.atts.len();
+        let found = self.file.with_page(page, |node| {
+            let (mut lo, mut hi) = (HEADER_SLOT + 1, node.num_slots());
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                if goes_right(&node.get_record(mid)?) {
+                    lo = mid + 1;
+                } else {
+                    hi = mid;
+                }
+            }
+
+            let slot = lo - 1;
+            let header = node.get_record(HEADER_SLOT)?;
+            if int_at(&header, 0)? != 0 {
+                return Some((slot, None));
+            }
+            let child = match slot {
+                HEADER_SLOT => int_at(&header, 1)?,
+                slot => int_at(&node.get_record(slot)?, key_len + 2)?,
+            };
+            Some((slot, Some(child as u64)))
+        })?;
+
+        found.ok_or_else(|| {
+            anyhow!(
+                "Corrupt node on page {page} of {}",
+                self.file.get_file_name()
+            )
+        })
+    }
+
+    // Goes down to the leaf holding the last entry `goes_right` holds for and gives its page and
+    // the slot of that entry
+    fn find_leaf(&self, goes_right: impl Fn(&Record) -> bool) -> Result<(u64, usize)> {
+        let mut page = self.root;
+        loop {
+            match self.search(page, &goes_right)? {
+                (slot, None) => return Ok((page, slot)),
+                (_, Some(child)) => page = child,
+            }
+        }
+    }
+
+    // The entries of the leaf on `page` from `first` on, along with the page of the next leaf
+    fn read_leaf(&self, page: u64, first: usize) -> Result<(Vec<Record>, Option<u64>)> {
+        let leaf = self.file.with_page(page, |leaf| {
+            let entries = (first..leaf.num_slots())
+                .map(|slot| leaf.get_record(slot))
+                .collect::<Option<Vec<_>>>()?;
+            let next = int_at(&leaf.get_record(HEADER_SLOT)?, 1)?;
+            Some((entries, (next >= 0).then_some(next as u64)))
+        })?;
+
+        leaf.ok_or_else(|| {
+            anyhow!(
+                "Corrupt node on page {page} of {}",
+                self.file.get_file_name()
+            )
+        })
+    }
+
+    // Adds `entry` below the node on `page`. If the node had to be split, gives the entry
+    // separating it from the new node and the page of that.
+    fn insert_into(&mut self, page: u64, entry: &Record) -> Result<Option<(Record, u64)>> {
+        let (slot, child) = self.search(page, |other| self.compare(other, entry).is_le())?;
+
+        match child {
+            // the entry is already there
+            None if slot != HEADER_SLOT && self.entry_is(page, slot, entry)? => Ok(None),
+            None => self.insert_entry(page, entry),
+            Some(child) => match self.insert_into(child, entry)? {
+                Some((separator, right)) => self.insert_entry(page, &with_child(&separator, right)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    // Puts `entry` in its place in the node on `page`, splitting the node if it's full
+    fn insert_entry(&mut self, page: u64, entry: &Record) -> Result<Option<(Record, u64)>> {
+        if self.try_insert(page, entry)? {
+            return Ok(None);
+        }
+
+        let (separator, right) = self.split(page)?;
+        let target = if self.compare(entry, &separator).is_lt() {
+            page
+        } else {
+            right
+        };
+        if !self.try_insert(target, entry)? {
+            return Err(anyhow!(
+                "No room for an entry of {}",
+                self.file.get_file_name()
+            ));
+        }
+
+        Ok(Some((separator, right)))
+    }
+
+    fn try_insert(&mut self, page: u64, entry: &Record) -> Result<bool> {
+        let (slot, _) = self.search(page, |other| self.compare(other, entry).is_lt())?;
+        let size = entry.to_binary().len() + SLOT_SIZE;
+        let capacity = self.node_capacity;
+
+        self.file.modify_page(page, |node| {
+            node.get_used_space() + size <= capacity && node.insert_at(slot + 1, entry)
+        })
+    }
+
+    // Moves the upper half of the entries, by size, of the node on `page` to a new node. Gives
+    // the entry separating the two nodes, without a child, along with the page of the new one.
+    fn split(&mut self, page: u64) -> Result<(Record, u64)> {
+        let (header, entries) = self.file.with_page(page, |node| {
+            let entries = (HEADER_SLOT + 1..node.num_slots())
+                .map(|slot| node.get_record(slot))
+                .collect::<Option<Vec<_>>>();
+            (node.get_record(HEADER_SLOT), entries)
+        })?;
+        let (Some(header), Some(entries)) = (header, entries) else {
+            return Err(anyhow!(
+                "Corrupt node on page {page} of {}",
+                self.file.get_file_name()
+            ));
+        };
+        let is_leaf = int_at(&header, 0) != Some(0);
+
+        let sizes = entries
+            .iter()
+            .map(|entry| entry.to_binary().len())
+            .collect::<Vec<_>>();
+        let total = sizes.iter().sum::<usize>();
+        let mut mid = 0;
+        let mut left = 0;
+        while mid < sizes.len() - 1 && left < total / 2 {
+            left += sizes[mid];
+            mid += 1;
+        }
+        let mid = mid.max(1);
+
+        let right = self.file.new_page()?;
+        let separator = if is_leaf {
+            // the new leaf comes between this one and the next one
+            let next = int_at(&header, 1).filter(|&next| next >= 0);
+            self.write_header(right, true, next.map(|next| next as u64))?;
+            self.write_header(page, true, Some(right))?;
+            self.append_entries(right, &entries[mid..])?;
+            entries[mid].clone()
+        } else {
+            // the separator moves up, its child becomes the first one of the new node
+            let key_len = self.key_ordering.atts.len();
+            let child = int_at(&entries[mid], key_len + 2).map(|child| child as u64);
+            self.write_header(right, false, child)?;
+            self.append_entries(right, &entries[mid + 1..])?;
+            without_child(&entries[mid], key_len)
+        };
+
+        let num_slots = HEADER_SLOT + 1 + entries.len();
+        self.file.modify_page(page, |node| {
+            for slot in (HEADER_SLOT + 1 + mid..num_slots).rev() {
+                node.remove_at(slot);
+            }
+        })?;
+
+        Ok((separator, right))
+    }
+
+    fn append_entries(&mut self, page: u64, entries: &[Record]) -> Result<()> {
+        self.file.modify_page(page, |node| {
+            entries
+                .iter()
+                .all(|entry| node.insert_at(node.num_slots(), entry))
+        })?;
+
+        Ok(())
+    }
+
+    // Sets whether the node on `page` is a leaf along with the page of the next leaf, or of the
+    // first child for inner nodes
+    fn write_header(&mut self, page: u64, is_leaf: bool, link: Option<u64>) -> Result<()> {
+        let mut header = Record::new();
+        header.push_int(is_leaf as i64);
+        header.push_int(link.map_or(-1, |link| link as i64));
+
+        self.write_record(page, HEADER_SLOT, &header)
+    }
+
+    fn write_meta(&mut self) -> Result<()> {
+        let mut meta = Record::new();
+        meta.push_int(self.root as i64);
+        for &(att, type_) in &self.ordering.atts {
+            meta.push_int(att as i64);
+            meta.push_int(type_ as i64);
+        }
+
+        self.write_record(META_PAGE, 0, &meta)
+    }
+
+    // Stores `record` in `slot` of `page`, which either holds a record already or is the first
+    // slot of an empty page
+    fn write_record(&mut self, page: u64, slot: usize, record: &Record) -> Result<()> {
+        let stored = self
+            .file
+            .modify_page(page, |node| match node.get_slot(slot) {
+                Some(_) => node.update(slot, record),
+                None => node.insert_at(slot, record),
+            })?;
+
+        if !stored {
+            return Err(anyhow!(
+                "No room for slot {slot} on page {page} of {}",
+                self.file.get_file_name()
+            ));
+        }
+        Ok(())
+    }
+}
+
+// The entry of a leaf for the record at `rid` with the given key
+fn new_entry(key: &Record, rid: RecordId) -> Record {
+    let mut entry = key.clone();
+    entry.push_int(rid.page as i64);
+    entry.push_int(rid.slot as i64);
+    entry
+}
+
+// The entry of an inner node, which also gives the child right of it
+fn with_child(entry: &Record, child: u64) -> Record {
+    let mut entry = entry.clone();
+    entry.push_int(child as i64);
+    entry
+}
+
+fn without_child(entry: &Record, key_len: usize) -> Record {
+    Record::from(entry.get_data()[..key_len + 2].to_vec())
+}
+
+fn int_at(record: &Record, at: usize) -> Option<i64> {
+    match record.get_column(at)? {
+        MappedAttrData::Integer(val) => Some(val),
+        _ => None,
+    }
+}
+
+// Compares entries by their keys, which are made of the attributes of `ordering`
+fn key_ordering(ordering: &OrderMaker) -> OrderMaker {
+    OrderMaker {
+        atts: ordering
+            .atts
+            .iter()
+            .enumerate()
+            .map(|(i, &(_, type_))| (i as i32, type_))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+    use tempfile::NamedTempFile;
+
+    fn int_key(val: i64) -> Record {
+        let mut key = Record::new();
+        key.push_int(val);
+        key
+    }
+
+    fn rid(i: u64) -> RecordId {
+        RecordId {
+            page: i / 100,
+            slot: (i % 100) as usize,
+        }
+    }
+
+    // an index on the first attribute with nodes small enough that the tree gets a few levels
+    fn small_index(path: &Path) -> BTreeIndex {
+        let ordering = OrderMaker {
+            atts: vec![(0, Type::Integer)],
+        };
+        let mut index = BTreeIndex::create(path, ordering).unwrap();
+        index.node_capacity = 1024;
+        index
+    }
+
+    #[test]
+    fn test_btree_insert_and_lookup() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut index = small_index(temp_file.path());
+
+        // inserted out of order, every key twice
+        let keys = (0..2000).map(|i| (i * 7919) % 1000).collect::<Vec<i64>>();
+        for (i, &key) in keys.iter().enumerate() {
+            index.insert(&int_key(key), rid(i as u64)).unwrap();
+        }
+        assert!(index.get_height().unwrap() >= 3);
+
+        for key in 0..1000 {
+            let mut expected = (0..2000)
+                .filter(|&i| keys[i] == key)
+                .map(|i| rid(i as u64))
+                .collect::<Vec<_>>();
+            expected.sort();
+            assert_eq!(index.lookup(&int_key(key)).unwrap(), expected);
+        }
+        assert!(index.lookup(&int_key(1000)).unwrap().is_empty());
+        assert!(index.lookup(&int_key(-1)).unwrap().is_empty());
+
+        // the same entry again doesn't add anything
+        index.insert(&int_key(keys[0]), rid(0)).unwrap();
+        assert_eq!(index.lookup(&int_key(keys[0])).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_btree_range() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut index = small_index(temp_file.path());
+        for i in (0..500).rev() {
+            index.insert(&int_key(i * 2), rid(i as u64)).unwrap();
+        }
+
+        let keys = |rids: Vec<RecordId>| {
+            rids.into_iter()
+                .map(|rid| (rid.page * 100 + rid.slot as u64) as i64 * 2)
+                .collect::<Vec<_>>()
+        };
+        let (ten, twenty) = (int_key(10), int_key(20));
+
+        let range = index.range(Bound::Included(&ten), Bound::Included(&twenty));
+        assert_eq!(keys(range.unwrap()), [10, 12, 14, 16, 18, 20]);
+        let range = index.range(Bound::Excluded(&ten), Bound::Excluded(&twenty));
+        assert_eq!(keys(range.unwrap()), [12, 14, 16, 18]);
+        let range = index.range(Bound::Excluded(&int_key(11)), Bound::Included(&int_key(15)));
+        assert_eq!(keys(range.unwrap()), [12, 14]);
+        let range = index.range(Bound::Unbounded, Bound::Excluded(&int_key(6)));
+        assert_eq!(keys(range.unwrap()), [0, 2, 4]);
+        let range = index.range(Bound::Included(&int_key(993)), Bound::Unbounded);
+        assert_eq!(keys(range.unwrap()), [994, 996, 998]);
+        let range = index.range(Bound::Unbounded, Bound::Unbounded);
+        assert_eq!(
+            keys(range.unwrap()),
+            (0..500).map(|i| i * 2).collect::<Vec<_>>()
+        );
+        let range = index.range(Bound::Included(&twenty), Bound::Included(&ten));
+        assert!(range.unwrap().is_empty());
+
+        let mut string_key = Record::new();
+        string_key.push_str("10");
+        assert!(index.lookup(&string_key).is_err());
+        assert!(index.lookup(&Record::new()).is_err());
+    }
+
+    #[test]
+    fn test_btree_delete() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut index = small_index(temp_file.path());
+        let mut expected = BTreeSet::new();
+        for i in 0..1000 {
+            index.insert(&int_key(i % 100), rid(i as u64)).unwrap();
+            expected.insert((i % 100, rid(i as u64)));
+        }
+
+        for i in (0..1000).filter(|i| i % 3 != 0) {
+            assert!(index.delete(&int_key(i % 100), rid(i as u64)).unwrap());
+            expected.remove(&(i % 100, rid(i as u64)));
+        }
+        assert!(!index.delete(&int_key(1), rid(1)).unwrap());
+        assert!(!index.delete(&int_key(2), rid(3)).unwrap());
+
+        for key in 0..100 {
+            let rids = expected
+                .range((key, rid(0))..=(key, rid(u64::MAX)))
+                .map(|&(_, rid)| rid)
+                .collect::<Vec<_>>();
+            assert_eq!(index.lookup(&int_key(key)).unwrap(), rids);
+        }
+        let all = index.range(Bound::Unbounded, Bound::Unbounded).unwrap();
+        assert_eq!(
+            all,
+            expected.iter().map(|&(_, rid)| rid).collect::<Vec<_>>()
+        );
+
+        // deleting everything leaves an empty index that still takes new entries
+        for &(key, rid) in &expected {
+            assert!(index.delete(&int_key(key), rid).unwrap());
+        }
+        assert!(
+            index
+                .range(Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+                .is_empty()
+        );
+        index.insert(&int_key(5), rid(5)).unwrap();
+        assert_eq!(index.lookup(&int_key(5)).unwrap(), [rid(5)]);
+    }
+
+    #[test]
+    fn test_btree_reopen() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut index = small_index(temp_file.path());
+        for i in 0..300 {
+            index.insert(&int_key(i), rid(i as u64)).unwrap();
+        }
+        let height = index.get_height().unwrap();
+        index.close().unwrap();
+
+        let index = BTreeIndex::open(temp_file.path()).unwrap();
+        assert_eq!(index.get_ordering().atts, [(0, Type::Integer)]);
+        assert_eq!(index.get_height().unwrap(), height);
+        assert_eq!(index.lookup(&int_key(123)).unwrap(), [rid(123)]);
+        assert_eq!(
+            index
+                .range(Bound::Unbounded, Bound::Unbounded)
+                .unwrap()
+                .len(),
+            300
+        );
+
+        let heap_file = NamedTempFile::new().unwrap();
+        DBFile::new()
+            .create(heap_file.path(), FileType::Heap)
+            .unwrap();
+        assert!(BTreeIndex::open(heap_file.path()).is_err());
+
+        // the nodes can't be written to like the pages of a heap file
+        let mut file = DBFile::new();
+        file.open(temp_file.path()).unwrap();
+        assert_eq!(file.get_file_type(), FileType::Index);
+        assert!(file.append_record(int_key(1)).is_err());
+    }
+
+    #[test]
+    fn test_btree_on_heap_file() {
+        let heap_path = NamedTempFile::new().unwrap();
+        let index_path = NamedTempFile::new().unwrap();
+
+        let mut file = DBFile::new();
+        file.create(heap_path.path(), FileType::Heap).unwrap();
+        // keyed by the name and then the id, the other way around from how they're stored
+        let ordering = OrderMaker {
+            atts: vec![(1, Type::String), (0, Type::Integer)],
+        };
+        let mut index = BTreeIndex::create(index_path.path(), ordering).unwrap();
+
+        for i in 0..5000 {
+            let mut record = Record::new();
+            record.push_int(i);
+            record.push_str(&format!("name {}", i % 50));
+            record.push_flt(i as f64 / 2.0);
+
+            let rid = file.append_record(record.clone()).unwrap();
+            index.insert(&record, rid).unwrap();
+        }
+        assert!(index.get_height().unwrap() >= 2);
+
+        let mut key = Record::new();
+        key.push_str("name 7");
+        key.push_int(407);
+        let rids = index.lookup(&key).unwrap();
+        assert_eq!(rids.len(), 1);
+        let record = file.get_record(rids[0]).unwrap();
+        assert_eq!(index.get_key(&record).unwrap(), key);
+
+        let mut lower = Record::new();
+        lower.push_str("name 7");
+        lower.push_null();
+        let mut upper = Record::new();
+        upper.push_str("name 8");
+        upper.push_null();
+        let rids = index
+            .range(Bound::Included(&lower), Bound::Excluded(&upper))
+            .unwrap();
+        let ids = rids
+            .iter()
+            .map(|&rid| file.get_record(rid).unwrap().get_projected_data(&[0]))
+            .collect::<Vec<_>>();
+        let expected = (0..100)
+            .map(|i| vec![ProjectedData::Integer(i * 50 + 7)])
+            .collect::<Vec<_>>();
+        assert_eq!(ids, expected);
+    }
+}
@@ -0,0 +1,369 @@
+use crate::db_file::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use anyhow::{Result, anyhow};
+
+// The buffer pool the data files share. It sits behind a mutex so the files of every operator
+// can use the same one.
+pub type SharedBufferPool = Arc<Mutex<BufferPool>>;
+
+// A page of a file that's kept in memory
+#[derive(Debug)]
+struct Frame {
+    // the file id and the page number, `None` while the frame is unused
+    key: Option<(usize, u64)>,
+    page: Page,
+    pin_count: usize,
+    // whether the page changed since it was read from disk
+    dirty: bool,
+    // set whenever the page gets used, so the clock gives it another round before evicting it
+    referenced: bool,
+}
+
+// Keeps a fixed number of pages in memory for all the files registered with it. Pages get
+// pinned while they're used and evicted with the clock algorithm once the frames run out,
+// writing them back to disk if they changed.
+#[derive(Debug)]
+pub struct BufferPool {
+    num_frames: usize,
+    // frames are only allocated once they're needed
+    frames: Vec<Frame>,
+    page_table: HashMap<(usize, u64), usize>,
+    files: HashMap<usize, File>,
+    next_file_id: usize,
+    clock_hand: usize,
+
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl BufferPool {
+    pub fn new(num_frames: usize) -> Self {
+        BufferPool {
+            num_frames: num_frames.max(1),
+            frames: Vec::new(),
+            page_table: HashMap::new(),
+            files: HashMap::new(),
+            next_file_id: 0,
+            clock_hand: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn shared(num_frames: usize) -> SharedBufferPool {
+        Arc::new(Mutex::new(BufferPool::new(num_frames)))
+    }
+
+    // The pool data files use unless they're given another one
+    pub fn global() -> SharedBufferPool {
+        static GLOBAL: OnceLock<SharedBufferPool> = OnceLock::new();
+
+        GLOBAL
+            .get_or_init(|| BufferPool::shared(crate::BUFFER_POOL_FRAMES))
+            .clone()
+    }
+
+    // Makes the pages of `file` available through the pool, the id identifies the file from then
+    // on
+    pub fn register_file(&mut self, file: File) -> usize {
+        let file_id = self.next_file_id;
+        self.next_file_id += 1;
+        self.files.insert(file_id, file);
+
+        file_id
+    }
+
+    // Writes back the changed pages of the file and drops all of its pages from the pool
+    pub fn close_file(&mut self, file_id: usize) -> Result<()> {
+        self.flush_file(file_id)?;
+
+        for frame in &mut self.frames {
+            if let Some(key) = frame.key
+                && key.0 == file_id
+            {
+                self.page_table.remove(&key);
+                frame.key = None;
+                frame.pin_count = 0;
+            }
+        }
+        self.files.remove(&file_id);
+
+        Ok(())
+    }
+
+    pub fn flush_file(&mut self, file_id: usize) -> Result<()> {
+        let file = self
+            .files
+            .get_mut(&file_id)
+            .ok_or_else(|| unknown_file(file_id))?;
+
+        for frame in &mut self.frames {
+            if let Some((id, page_num)) = frame.key
+                && id == file_id
+                && frame.dirty
+            {
+                frame.page.write_to(file, page_num)?;
+                frame.dirty = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Gives the frame holding page `page_num` of the file, reading it from disk if it isn't in
+    // the pool yet. The page stays in that frame until it gets unpinned again.
+    pub fn pin(&mut self, file_id: usize, page_num: u64) -> Result<usize> {
+        if let Some(&frame) = self.page_table.get(&(file_id, page_num)) {
+            self.hits += 1;
+            self.frames[frame].pin_count += 1;
+            self.frames[frame].referenced = true;
+            return Ok(frame);
+        }
+
+        self.misses += 1;
+        let frame = self.victim()?;
+        let file = self
+            .files
+            .get_mut(&file_id)
+            .ok_or_else(|| unknown_file(file_id))?;
+        self.frames[frame].page.read_from(file, page_num)?;
+        self.load(frame, file_id, page_num, false);
+
+        Ok(frame)
+    }
+
+    // Pins a new, empty page `page_num` of the file without reading anything from disk
+    pub fn pin_new(&mut self, file_id: usize, page_num: u64) -> Result<usize> {
+        if !self.files.contains_key(&file_id) {
+            return Err(unknown_file(file_id));
+        }
+        if let Some(&frame) = self.page_table.get(&(file_id, page_num)) {
+            self.frames[frame].page.empty_it_out();
+            self.frames[frame].pin_count += 1;
+            self.frames[frame].dirty = true;
+            return Ok(frame);
+        }
+
+        let frame = self.victim()?;
+        self.frames[frame].page.empty_it_out();
+        self.load(frame, file_id, page_num, true);
+
+        Ok(frame)
+    }
+
+    pub fn unpin(&mut self, frame: usize, dirty: bool) {
+        let frame = &mut self.frames[frame];
+        frame.pin_count = frame.pin_count.saturating_sub(1);
+        frame.dirty |= dirty;
+    }
+
+    pub fn get_page(&self, frame: usize) -> &Page {
+        &self.frames[frame].page
+    }
+
+    pub fn get_page_mut(&mut self, frame: usize) -> &mut Page {
+        &mut self.frames[frame].page
+    }
+
+    pub fn get_num_frames(&self) -> usize {
+        self.num_frames
+    }
+
+    pub fn get_hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn get_misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn get_evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    fn load(&mut self, frame: usize, file_id: usize, page_num: u64, dirty: bool) {
+        let key = (file_id, page_num);
+        self.frames[frame].key = Some(key);
+        self.frames[frame].pin_count = 1;
+        self.frames[frame].dirty = dirty;
+        self.frames[frame].referenced = true;
+        self.page_table.insert(key, frame);
+    }
+
+    // Finds a frame for another page, evicting the first unpinned page the clock finds that
+    // wasn't used since the hand last passed it
+    fn victim(&mut self) -> Result<usize> {
+        if let Some(frame) = self.frames.iter().position(|frame| frame.key.is_none()) {
+            return Ok(frame);
+        }
+        if self.frames.len() < self.num_frames {
+            self.frames.push(Frame {
+                key: None,
+                page: Page::new(),
+                pin_count: 0,
+                dirty: false,
+                referenced: false,
+            });
+            return Ok(self.frames.len() - 1);
+        }
+
+        // two rounds, the first one might only clear the reference bits
+        for _ in 0..2 * self.frames.len() {
+            let frame = self.clock_hand;
+            self.clock_hand = (self.clock_hand + 1) % self.frames.len();
+
+            let candidate = &mut self.frames[frame];
+            if candidate.pin_count > 0 {
+                continue;
+            }
+            if candidate.referenced {
+                candidate.referenced = false;
+                continue;
+            }
+
+            let key = candidate.key.unwrap();
+            if candidate.dirty {
+                let file = self
+                    .files
+                    .get_mut(&key.0)
+                    .ok_or_else(|| unknown_file(key.0))?;
+                candidate.page.write_to(file, key.1)?;
+                candidate.dirty = false;
+            }
+            candidate.key = None;
+            self.page_table.remove(&key);
+            self.evictions += 1;
+
+            return Ok(frame);
+        }
+
+        Err(anyhow!(
+            "All {} frames of the buffer pool are pinned",
+            self.frames.len()
+        ))
+    }
+}
+
+fn unknown_file(file_id: usize) -> anyhow::Error {
+    anyhow!("File {file_id} isn't registered with the buffer pool")
+}
+
+// A panic while the pool was locked doesn't leave it in a state other files can't use
+pub fn lock_buffer_pool(pool: &SharedBufferPool) -> MutexGuard<'_, BufferPool> {
+    pool.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+    use tempfile::NamedTempFile;
+
+    // big enough that every page only holds one of them
+    fn big_record(i: i64) -> Record {
+        let mut record = Record::new();
+        record.push_int(i);
+        record.push_str(&"x".repeat(100_000));
+        record
+    }
+
+    fn create_file(pool: &SharedBufferPool, path: &std::path::Path, records: i64) -> DBFile {
+        let mut file = DBFile::new();
+        file.set_buffer_pool(pool.clone()).unwrap();
+        file.create(path, FileType::Heap).unwrap();
+        for i in 0..records {
+            file.append_record(big_record(i)).unwrap();
+        }
+
+        file
+    }
+
+    #[test]
+    fn test_eviction_writes_back_dirty_pages() {
+        let pool = BufferPool::shared(2);
+        let temp_file = NamedTempFile::new().unwrap();
+
+        // five pages through two frames, so the first three have to be written out to make room
+        let mut file = create_file(&pool, temp_file.path(), 5);
+        assert_eq!(lock_buffer_pool(&pool).get_evictions(), 3);
+        assert_eq!(
+            std::fs::metadata(temp_file.path()).unwrap().len(),
+            16 + 3 * PAGE_SIZE as u64
+        );
+
+        let rid = RecordId { page: 0, slot: 0 };
+        file.update(rid, big_record(100)).unwrap();
+        assert_eq!(file.get_record(rid).unwrap(), big_record(100));
+
+        file.move_first();
+        let mut record = Record::new();
+        let mut ids = Vec::new();
+        while file.get_next(&mut record).unwrap() {
+            ids.push(record.get_projected_data(&[0]));
+        }
+        assert_eq!(ids.concat(), [100, 1, 2, 3, 4].map(ProjectedData::Integer));
+        file.close().unwrap();
+
+        let mut file = DBFile::new();
+        file.open(temp_file.path()).unwrap();
+        assert_eq!(file.get_num_pages(), 5);
+        assert_eq!(file.get_record(rid).unwrap(), big_record(100));
+    }
+
+    #[test]
+    fn test_hits_and_misses() {
+        let pool = BufferPool::shared(4);
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut file = create_file(&pool, temp_file.path(), 3);
+        file.close().unwrap();
+
+        let mut file = DBFile::new();
+        file.set_buffer_pool(pool.clone()).unwrap();
+        file.open(temp_file.path()).unwrap();
+
+        let (hits, misses) = {
+            let pool = lock_buffer_pool(&pool);
+            (pool.get_hits(), pool.get_misses())
+        };
+        for _ in 0..2 {
+            file.move_first();
+            while file.get_next(&mut Record::new()).unwrap() {}
+        }
+
+        // the first scan reads every page once, the second one finds them all in the pool
+        let pool = lock_buffer_pool(&pool);
+        assert_eq!(pool.get_misses() - misses, 3);
+        assert!(pool.get_hits() - hits >= 3);
+        assert_eq!(pool.get_evictions(), 0);
+    }
+
+    #[test]
+    fn test_pinned_pages_stay() {
+        let pool = BufferPool::shared(2);
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut file = create_file(&pool, temp_file.path(), 3);
+        file.flush().unwrap();
+
+        let mut pool = lock_buffer_pool(&pool);
+        let file_id = pool.register_file(std::fs::File::open(temp_file.path()).unwrap());
+        let first = pool.pin(file_id, 0).unwrap();
+        let second = pool.pin(file_id, 1).unwrap();
+        assert!(pool.pin(file_id, 2).is_err());
+
+        // the clock skips the pinned page and takes the unpinned one
+        pool.unpin(second, false);
+        let third = pool.pin(file_id, 2).unwrap();
+        assert_eq!(third, second);
+        assert_eq!(pool.pin(file_id, 0).unwrap(), first);
+        assert_eq!(pool.get_page(first).get_record(0), Some(big_record(0)));
+        assert_eq!(pool.get_page(third).get_record(0), Some(big_record(2)));
+
+        assert!(pool.pin(file_id, 3).is_err());
+        assert!(pool.pin(file_id + 1, 0).is_err());
+    }
+}
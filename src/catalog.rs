@@ -1,14 +1,19 @@
 #![allow(dead_code)]
 #![allow(unused)]
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+use crate::db_file::*;
+use crate::record::*;
 use crate::schema::*;
 use crate::types::*;
 
 use anyhow::{Result, anyhow};
 use rusqlite::{Connection, params};
 
+// number of buckets in the histograms built by `analyze`
+const HISTOGRAM_BUCKETS: usize = 10;
+
 pub struct Catalog {
     conn: Connection,
 
@@ -35,6 +40,7 @@ impl Catalog {
         conn.execute_batch("
             CREATE TABLE IF NOT EXISTS Tables (name VARCHAR, num_tuples INT, file VARCHAR);
             CREATE TABLE IF NOT EXISTS Attributes (table_name VARCHAR, position INT, name VARCHAR, type VARCHAR, num_distinct INT);
+            CREATE TABLE IF NOT EXISTS Statistics (table_name VARCHAR, position INT, min REAL, max REAL, histogram VARCHAR);
         ");
 
         let mut stmt = conn.prepare("SELECT name, num_tuples, file FROM Tables;")?;
@@ -77,6 +83,49 @@ impl Catalog {
         drop(rows);
         stmt.finalize()?;
 
+        let mut stmt =
+            conn.prepare("SELECT table_name, position, min, max, histogram FROM Statistics;")?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            let table_name: String = row.get("table_name")?;
+            let position: usize = row.get("position")?;
+            let min: f64 = row.get("min")?;
+            let max: f64 = row.get("max")?;
+            let histogram: Option<String> = row.get("histogram")?;
+
+            // the histogram bounds are kept as a space separated list
+            let histogram = match histogram {
+                Some(histogram) => histogram
+                    .split_whitespace()
+                    .map(|bound| bound.parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => Vec::new(),
+            };
+
+            let Some(schema) = table_schema.get_mut(&table_name) else {
+                return Err(anyhow!("Statistics for unknown table {table_name}"));
+            };
+            let Some(att) = schema.get_atts().get(position) else {
+                return Err(anyhow!(
+                    "Statistics for unknown attribute {position} of {table_name}"
+                ));
+            };
+
+            let name = att.name.clone();
+            schema.set_statistics(
+                &name,
+                Some(Statistics {
+                    min,
+                    max,
+                    histogram,
+                }),
+            );
+        }
+
+        drop(rows);
+        stmt.finalize()?;
+
         Ok(Catalog { table_schema, conn })
     }
 
@@ -86,6 +135,7 @@ impl Catalog {
             BEGIN TRANSACTION;
             DELETE FROM Tables;
             DELETE FROM Attributes;
+            DELETE FROM Statistics;
         ",
         )?;
 
@@ -129,11 +179,84 @@ impl Catalog {
 
         stmt.finalize()?;
 
+        let mut stmt = self
+            .conn
+            .prepare("INSERT INTO Statistics VALUES(?, ?, ?, ?, ?);")?;
+
+        for (table_name, schema) in self.table_schema.iter() {
+            for (pos, att) in schema.get_atts().iter().enumerate() {
+                let Some(statistics) = &att.statistics else {
+                    continue;
+                };
+
+                let histogram = (!statistics.histogram.is_empty()).then(|| {
+                    statistics
+                        .histogram
+                        .iter()
+                        .map(|bound| bound.to_string())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                });
+
+                stmt.execute(params![
+                    table_name,
+                    pos,
+                    statistics.min,
+                    statistics.max,
+                    histogram
+                ]);
+            }
+        }
+
+        stmt.finalize()?;
+
         self.conn.execute("COMMIT;", []);
 
         Ok(())
     }
 
+    // Recomputes the statistics of `table` from its data file: the number of records, the exact
+    // number of distinct values of every attribute and, for numeric attributes, their range and
+    // histogram. Nulls don't count as values. The catalog gets saved afterwards.
+    pub fn analyze(&mut self, table: &str) -> Result<()> {
+        let Some(schema) = self.table_schema.get(table) else {
+            return Err(anyhow!("Table {table} doesn't exist"));
+        };
+        let schema = schema.clone();
+
+        let mut file = DBFile::new();
+        file.open(schema.get_f_path())?;
+        file.set_schema(schema.clone());
+
+        let atts = schema.get_atts();
+        let mut no_tuples = 0;
+        let mut distincts = vec![HashSet::<ProjectedData>::new(); atts.len()];
+        let mut values = vec![Vec::<f64>::new(); atts.len()];
+
+        let mut record = Record::new();
+        while file.get_next(&mut record)? {
+            no_tuples += 1;
+
+            for (i, value) in record.get_data().into_iter().enumerate() {
+                match value {
+                    MappedAttrData::Null => continue,
+                    MappedAttrData::Integer(val) => values[i].push(val as f64),
+                    MappedAttrData::Float(val) => values[i].push(val),
+                    MappedAttrData::String(_) => (),
+                }
+                distincts[i].insert(value.into());
+            }
+        }
+
+        self.set_no_tuples(table, no_tuples);
+        for (att, (distincts, values)) in atts.iter().zip(distincts.into_iter().zip(values)) {
+            self.set_no_distinct(table, &att.name, distincts.len() as u64);
+            self.set_statistics(table, &att.name, build_statistics(values));
+        }
+
+        self.save()
+    }
+
     pub fn get_no_tuples(&self, table: &str) -> Option<u64> {
         Some(self.table_schema.get(table)?.get_no_tuples())
     }
@@ -171,6 +294,22 @@ impl Catalog {
         schema.set_distincts(attribute, no_distinct)
     }
 
+    pub fn get_statistics(&self, table: &str, attribute: &str) -> Option<&Statistics> {
+        self.table_schema.get(table)?.get_statistics(attribute)
+    }
+
+    pub fn set_statistics(
+        &mut self,
+        table: &str,
+        attribute: &str,
+        statistics: Option<Statistics>,
+    ) -> bool {
+        let Some(schema) = self.table_schema.get_mut(table) else {
+            return false;
+        };
+        schema.set_statistics(attribute, statistics)
+    }
+
     pub fn get_tables(&self) -> Vec<String> {
         self.table_schema
             .iter()
@@ -225,6 +364,26 @@ impl Catalog {
     }
 }
 
+// The range and an equi-depth histogram of the values of a numeric attribute, `None` if it only
+// has nulls or isn't numeric
+fn build_statistics(mut values: Vec<f64>) -> Option<Statistics> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_by(f64::total_cmp);
+    let last = values.len() - 1;
+    let histogram = (0..=HISTOGRAM_BUCKETS)
+        .map(|i| values[i * last / HISTOGRAM_BUCKETS])
+        .collect();
+
+    Some(Statistics {
+        min: values[0],
+        max: values[last],
+        histogram,
+    })
+}
+
 impl std::fmt::Display for Catalog {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for (name, schema) in self.table_schema.iter() {
@@ -234,3 +393,52 @@ impl std::fmt::Display for Catalog {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_statistics() -> Result<()> {
+        let mut catalog = Catalog::catalog_from_sql(
+            "
+            CREATE TABLE Tables (name VARCHAR, num_tuples INT, file VARCHAR);
+            CREATE TABLE Attributes (table_name VARCHAR, position INT, name VARCHAR, type VARCHAR, num_distinct INT);
+            INSERT INTO Tables VALUES('lineitem', 100, 'lineitem.dat');
+            INSERT INTO Attributes VALUES('lineitem', 0, 'l_orderkey', 'INTEGER', 25);
+            INSERT INTO Attributes VALUES('lineitem', 1, 'l_discount', 'FLOAT', 11);
+            ",
+        )?;
+
+        assert_eq!(catalog.get_statistics("lineitem", "l_discount"), None);
+
+        let discount = Statistics {
+            min: 0.0,
+            max: 0.1,
+            histogram: vec![0.0, 0.02, 0.05, 0.07, 0.1],
+        };
+        let orderkey = Statistics {
+            min: 1.0,
+            max: 100.0,
+            histogram: Vec::new(),
+        };
+        assert!(catalog.set_statistics("lineitem", "l_discount", Some(discount.clone())));
+        assert!(catalog.set_statistics("lineitem", "l_orderkey", Some(orderkey.clone())));
+        assert!(!catalog.set_statistics("lineitem", "l_tax", None));
+        assert!(!catalog.set_statistics("orders", "o_orderkey", None));
+        catalog.save()?;
+
+        let catalog = Catalog::from_conn(catalog.conn)?;
+        assert_eq!(
+            catalog.get_statistics("lineitem", "l_discount"),
+            Some(&discount)
+        );
+        assert_eq!(
+            catalog.get_statistics("lineitem", "l_orderkey"),
+            Some(&orderkey)
+        );
+        assert_eq!(catalog.get_no_distinct("lineitem", "l_discount"), Some(11));
+
+        Ok(())
+    }
+}
@@ -24,7 +24,7 @@ pub struct OrderMaker {
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Disjunction {
-    or_list: Vec<Comparison>,
+    pub or_list: Vec<Comparison>,
 }
 
 impl Disjunction {
@@ -205,28 +205,105 @@ impl Cnf {
             .map(|att| att.name.clone())
             .collect();
 
-        projected.and_list.retain(|disjunction| {
-            disjunction.or_list.iter().all(|mut comparison| {
-                let att_name1 = cur_schema.get_atts()[comparison.which_att1 as usize].name.clone();
-                let att_name2 = cur_schema.get_atts()[comparison.which_att2 as usize].name.clone();
+        // literal operands index into the constants record rather than the schema, so they are
+        // left untouched
+        let in_target = |operand: Target, which_att: i32| {
+            operand == Target::Literal
+                || target_atts.contains(&cur_schema.get_atts()[which_att as usize].name)
+        };
 
-                target_atts.contains(&att_name1) && target_atts.contains(&att_name2)
+        projected.and_list.retain(|disjunction| {
+            disjunction.or_list.iter().all(|comparison| {
+                in_target(comparison.operand1, comparison.which_att1)
+                    && in_target(comparison.operand2, comparison.which_att2)
             })
         });
 
+        let remap = |operand: Target, which_att: &mut i32| {
+            if operand != Target::Literal {
+                let att_name = &cur_schema.get_atts()[*which_att as usize].name;
+                *which_att = target_schema.index_of(att_name).unwrap() as i32;
+            }
+        };
+
         projected.and_list.iter_mut().for_each(|disjunction| {
             disjunction.or_list.iter_mut().for_each(|comparison| {
-                let att_name1 = cur_schema.get_atts()[comparison.which_att1 as usize].name.clone();
-                let att_name2 = cur_schema.get_atts()[comparison.which_att2 as usize].name.clone();
-
-                comparison.which_att1 = target_schema.index_of(&att_name1).unwrap() as i32;
-                comparison.which_att2 = target_schema.index_of(&att_name2).unwrap() as i32;
+                remap(comparison.operand1, &mut comparison.which_att1);
+                remap(comparison.operand2, &mut comparison.which_att2);
             })
         });
 
         projected
     }
 
+    // Splits the cnf into the disjunctions that compare against a literal and those that only
+    // compare attributes, in that order. Only the latter can be evaluated by a join.
+    pub fn partition_literals(self) -> (Cnf, Cnf) {
+        let (literal, attribute) = self.and_list.into_iter().partition(|disjunction| {
+            disjunction.or_list.iter().any(|comparison| {
+                comparison.operand1 == Target::Literal || comparison.operand2 == Target::Literal
+            })
+        });
+
+        (
+            Cnf {
+                and_list: literal,
+                is_false: self.is_false,
+            },
+            Cnf {
+                and_list: attribute,
+                is_false: self.is_false,
+            },
+        )
+    }
+
+    // Splits off the disjunctions that only compare literals with each other, and a cnf that is
+    // false as a whole, in that order. Those don't depend on any attribute, so they can be
+    // evaluated anywhere in a plan.
+    pub fn partition_constant(self) -> (Cnf, Cnf) {
+        let (constant, attribute) = self.and_list.into_iter().partition(|disjunction| {
+            disjunction.or_list.iter().all(|comparison| {
+                comparison.operand1 == Target::Literal && comparison.operand2 == Target::Literal
+            })
+        });
+
+        (
+            Cnf {
+                and_list: constant,
+                is_false: self.is_false,
+            },
+            Cnf {
+                and_list: attribute,
+                is_false: false,
+            },
+        )
+    }
+
+    // Takes a cnf compiled against the concatenation of a left and a right schema (so every
+    // attribute is `Target::Left`) and retargets the attributes past `left_atts` to the right
+    // record, so it can be run on the two records of a join separately
+    pub fn into_join_predicate(mut self, left_atts: usize) -> Cnf {
+        let retarget = |operand: &mut Target, which_att: &mut i32| {
+            if *operand == Target::Left && *which_att as usize >= left_atts {
+                *operand = Target::Right;
+                *which_att -= left_atts as i32;
+            }
+        };
+
+        for disjunction in &mut self.and_list {
+            for comparison in &mut disjunction.or_list {
+                retarget(&mut comparison.operand1, &mut comparison.which_att1);
+                retarget(&mut comparison.operand2, &mut comparison.which_att2);
+            }
+        }
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.and_list.is_empty() && !self.is_false
+    }
+
     pub fn minimize(&mut self) {
         // TODO:
     }
@@ -298,6 +375,54 @@ impl Cnf {
         })
     }
 
+    // Whether the predicate can be evaluated by a hash or merge join, i.e. it compares the two
+    // sides and every such comparison is an equality that has to hold on its own (isn't in an OR)
+    pub fn is_equi_join(&self) -> bool {
+        let is_join = |comparison: &Comparison| {
+            matches!(
+                (comparison.operand1, comparison.operand2),
+                (Target::Left, Target::Right) | (Target::Right, Target::Left)
+            )
+        };
+
+        let mut has_join = false;
+        for disjunction in &self.and_list {
+            if !disjunction.or_list.iter().any(is_join) {
+                continue;
+            }
+
+            match disjunction.or_list.as_slice() {
+                [comparison] if comparison.op == CompOp::Equal => has_join = true,
+                _ => return false,
+            }
+        }
+
+        has_join && !self.is_false
+    }
+
+    // The (left, right) pairs of attributes that the predicate requires to be equal
+    pub fn equi_join_atts(&self) -> Vec<(usize, usize)> {
+        self.and_list
+            .iter()
+            .filter_map(|disjunction| match disjunction.or_list.as_slice() {
+                [comparison] if comparison.op == CompOp::Equal => {
+                    match (comparison.operand1, comparison.operand2) {
+                        (Target::Left, Target::Right) => Some((
+                            comparison.which_att1 as usize,
+                            comparison.which_att2 as usize,
+                        )),
+                        (Target::Right, Target::Left) => Some((
+                            comparison.which_att2 as usize,
+                            comparison.which_att1 as usize,
+                        )),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn get_sort_orders(&self) -> (OrderMaker, OrderMaker) {
         let mut left = OrderMaker::default();
         let mut right = OrderMaker::default();
@@ -493,19 +618,23 @@ impl Comparison {
                 || self.operand2 == other.operand2 && self.which_att2 == other.which_att2)
     }
 
+    // Literals are read from `right`, which is where `Select` passes its constants record
     pub fn run(&self, left: &Record, right: &Record) -> bool {
         let left_val = match self.operand1 {
-            Target::Left => &left.get_data()[self.which_att1 as usize],
-            Target::Right => &right.get_data()[self.which_att1 as usize],
-            Target::Literal => panic!("literal not supported"),
+            Target::Left => &left.get_column(self.which_att1 as usize).unwrap(),
+            Target::Right | Target::Literal => &right.get_column(self.which_att1 as usize).unwrap(),
         };
 
         let right_val = match self.operand2 {
-            Target::Left => &left.get_data()[self.which_att2 as usize],
-            Target::Right => &right.get_data()[self.which_att2 as usize],
-            Target::Literal => panic!("literal not supported"),
+            Target::Left => &left.get_column(self.which_att2 as usize).unwrap(),
+            Target::Right | Target::Literal => &right.get_column(self.which_att2 as usize).unwrap(),
         };
 
+        // like in SQL, nothing compares true against a null, not even another null
+        if *left_val == MappedAttrData::Null || *right_val == MappedAttrData::Null {
+            return false;
+        }
+
         macro_rules! compare {
             ($attr_type:ident) => {{
                 let left_val = match left_val {
@@ -556,6 +685,18 @@ impl std::fmt::Display for Comparison {
     }
 }
 
+// Nulls sort before every other value. Returns `None` if neither side is null.
+fn compare_nulls(left: &MappedAttrData, right: &MappedAttrData) -> Option<std::cmp::Ordering> {
+    use std::cmp::Ordering;
+
+    match (left, right) {
+        (MappedAttrData::Null, MappedAttrData::Null) => Some(Ordering::Equal),
+        (MappedAttrData::Null, _) => Some(Ordering::Less),
+        (_, MappedAttrData::Null) => Some(Ordering::Greater),
+        _ => None,
+    }
+}
+
 impl OrderMaker {
     pub fn new(schema: &Schema) -> Self {
         Self {
@@ -572,9 +713,7 @@ impl OrderMaker {
         Self {
             atts: atts
                 .iter()
-                .filter_map(|&att| schema.get_atts().get(att as usize))
-                .enumerate()
-                .map(|(i, att)| (i as i32, att.type_))
+                .filter_map(|&att| Some((att, schema.get_atts().get(att as usize)?.type_)))
                 .collect(),
         }
     }
@@ -597,6 +736,13 @@ impl OrderMaker {
             let left_data = &left.get_data()[*att_idx as usize];
             let right_data = &right.get_data()[*att_idx as usize];
 
+            if let Some(cmp) = compare_nulls(left_data, right_data) {
+                if cmp != Ordering::Equal {
+                    return cmp;
+                }
+                continue;
+            }
+
             let cmp = match att_type {
                 Type::Integer => {
                     let left_val = match left_data {
@@ -659,6 +805,13 @@ impl OrderMaker {
             let left_data = &left.get_data()[left_att_idx as usize];
             let right_data = &right.get_data()[right_att_idx as usize];
 
+            if let Some(cmp) = compare_nulls(left_data, right_data) {
+                if cmp != Ordering::Equal {
+                    return cmp;
+                }
+                continue;
+            }
+
             let cmp = match (left_att_type, right_att_type) {
                 (Type::Integer, Type::Integer) => {
                     let left_val = match left_data {
@@ -17,6 +17,16 @@ impl std::fmt::Display for LalrpopError {
     }
 }
 
+#[derive(Debug)]
+pub enum Statement {
+    Query(Query),
+    // ANALYZE [table], refreshes the statistics of the table in the catalog
+    Analyze { table: String },
+    // EXPLAIN (ANALYZE)? [Query], shows the plan instead of the records. With ANALYZE the query
+    // gets run to compare the estimates with what actually happened.
+    Explain { query: Query, analyze: bool },
+}
+
 #[derive(Debug)]
 pub enum Query {
     // SELECT (DISTINCT)? [Atts] FROM [Query] (WHERE [Condition])?
@@ -26,8 +36,10 @@ pub enum Query {
         r#where: Option<Condition>,
         distinct: bool,
     },
+    // [Query] GROUP BY [Atts] (HAVING [Condition])?
     GroupBy {
         atts: GroupByAtts,
+        having: Option<Condition>,
         from: Box<Query>,
     },
     OrderBy {
@@ -35,6 +47,12 @@ pub enum Query {
         atts: OrderByAtts,
         from: Box<Query>,
     },
+    // [Query] LIMIT [limit] (OFFSET [offset])?
+    Limit {
+        limit: usize,
+        offset: usize,
+        from: Box<Query>,
+    },
     Join {
         join_type: JoinType,
         left: Box<Query>,
@@ -46,14 +64,6 @@ pub enum Query {
     },
 }
 
-#[derive(Debug)]
-pub enum JoinType {
-    Inner,
-    LeftOuter,
-    RightOuter,
-    FullOuter,
-}
-
 #[derive(Debug)]
 pub enum SelectAtts {
     Star,
@@ -66,9 +76,52 @@ pub enum SelectArg {
     Aggregate(Aggregate),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Aggregate {
     Sum(ArithExpr),
+    Count(ArithExpr),
+    CountDistinct(ArithExpr),
+    CountStar,
+    Avg(ArithExpr),
+    Min(ArithExpr),
+    Max(ArithExpr),
+}
+
+impl Aggregate {
+    pub fn kind(&self) -> AggregateKind {
+        match self {
+            Aggregate::Sum(_) => AggregateKind::Sum,
+            Aggregate::Count(_) | Aggregate::CountStar => AggregateKind::Count,
+            Aggregate::CountDistinct(_) => AggregateKind::CountDistinct,
+            Aggregate::Avg(_) => AggregateKind::Avg,
+            Aggregate::Min(_) => AggregateKind::Min,
+            Aggregate::Max(_) => AggregateKind::Max,
+        }
+    }
+
+    // `None` for COUNT(*)
+    pub fn expr(&self) -> Option<&ArithExpr> {
+        match self {
+            Aggregate::Sum(expr)
+            | Aggregate::Count(expr)
+            | Aggregate::CountDistinct(expr)
+            | Aggregate::Avg(expr)
+            | Aggregate::Min(expr)
+            | Aggregate::Max(expr) => Some(expr),
+            Aggregate::CountStar => None,
+        }
+    }
+}
+
+// Used as the name of the aggregate's attribute in the output schema
+impl std::fmt::Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Aggregate::CountStar => write!(f, "COUNT(*)"),
+            Aggregate::CountDistinct(expr) => write!(f, "COUNT(DISTINCT {})", expr),
+            _ => write!(f, "{}({})", self.kind(), self.expr().unwrap()),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -85,6 +138,8 @@ pub struct OrderByAtts {
 pub enum ConditionExpr {
     StrLit(String),
     Arith(ArithExpr),
+    // only valid in a HAVING clause
+    Aggregate(Aggregate),
 }
 
 #[derive(Debug)]
@@ -96,3 +151,26 @@ pub enum Condition {
     Or(Box<Condition>, Box<Condition>),
     Not(Box<Condition>),
 }
+
+impl Condition {
+    // All the aggregates referenced in the condition
+    pub fn aggregates(&self) -> Vec<&Aggregate> {
+        match self {
+            Condition::BoolLiteral(_) => Vec::new(),
+            Condition::Comparison(left, right, _) => [left, right]
+                .into_iter()
+                .filter_map(|expr| match expr.as_ref() {
+                    ConditionExpr::Aggregate(aggregate) => Some(aggregate),
+                    _ => None,
+                })
+                .collect(),
+
+            Condition::And(left, right) | Condition::Or(left, right) => {
+                let mut aggregates = left.aggregates();
+                aggregates.extend(right.aggregates());
+                aggregates
+            }
+            Condition::Not(internal) => internal.aggregates(),
+        }
+    }
+}
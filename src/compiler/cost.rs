@@ -0,0 +1,442 @@
+use crate::*;
+
+// Costs are counted in pages read or written. Handling a record in memory (hashing, comparing or
+// copying it) is a lot cheaper than that.
+pub const PAGE_IO_COST: f64 = 1.0;
+pub const RECORD_CPU_COST: f64 = 0.001;
+
+// the catalog doesn't know how long strings are, so this is a guess
+const AVERAGE_STRING_LEN: f64 = 20.0;
+
+// used for attributes the catalog doesn't have a number of distinct values for
+const DEFAULT_DISTINCTS: f64 = 10.0;
+
+// the usual guess for the fraction of records a range comparison keeps, when the catalog has no
+// statistics for the attribute
+const RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+
+// Estimated size of a record in memory, which is also what decides how many fit on a page
+pub fn record_size(schema: &Schema) -> f64 {
+    let data = schema
+        .get_atts()
+        .iter()
+        .map(|att| match att.type_ {
+            Type::String => size_of::<AttrData>() as f64 + AVERAGE_STRING_LEN + 1.0,
+            _ => size_of::<AttrData>() as f64,
+        })
+        .sum::<f64>();
+
+    // a page keeps 8 bytes of overhead per record
+    data + 8.0
+}
+
+// Estimated size of the whole relation in memory
+pub fn size(schema: &Schema) -> f64 {
+    schema.get_no_tuples() as f64 * record_size(schema)
+}
+
+pub fn pages(schema: &Schema) -> f64 {
+    let records_per_page = (PAGE_SIZE as f64 / record_size(schema))
+        .floor()
+        .clamp(1.0, MAX_RECORDS_PER_PAGE as f64);
+
+    (schema.get_no_tuples() as f64 / records_per_page).ceil()
+}
+
+fn fits_in_memory(schema: &Schema, memory_limit: usize) -> bool {
+    size(schema) <= memory_limit as f64
+}
+
+pub fn scan_cost(schema: &Schema) -> f64 {
+    pages(schema) * PAGE_IO_COST + schema.get_no_tuples() as f64 * RECORD_CPU_COST
+}
+
+// The right side is kept in memory and every left record gets compared with all of it
+pub fn nested_loop_join_cost(left: &Schema, right: &Schema) -> f64 {
+    left.get_no_tuples() as f64 * right.get_no_tuples() as f64 * RECORD_CPU_COST
+}
+
+// Every record gets hashed once. If the build side doesn't fit in memory, both sides are written
+// out to partitions and read back in.
+pub fn hash_join_cost(build: &Schema, probe: &Schema, memory_limit: usize) -> f64 {
+    let mut cost = (build.get_no_tuples() + probe.get_no_tuples()) as f64 * RECORD_CPU_COST;
+
+    if !fits_in_memory(build, memory_limit) {
+        cost += 2.0 * (pages(build) + pages(probe)) * PAGE_IO_COST;
+    }
+
+    cost
+}
+
+// n log n comparisons, plus writing out the sorted runs and reading them back in if the input
+// doesn't fit in memory
+pub fn sort_cost(schema: &Schema, memory_limit: usize) -> f64 {
+    let tuples = schema.get_no_tuples().max(1) as f64;
+    let mut cost = tuples * tuples.log2().max(1.0) * RECORD_CPU_COST;
+
+    if !fits_in_memory(schema, memory_limit) {
+        cost += 2.0 * pages(schema) * PAGE_IO_COST;
+    }
+
+    cost
+}
+
+// Both sides are read once, after sorting the ones that aren't sorted on the join keys already
+pub fn merge_join_cost(
+    left: &Schema,
+    right: &Schema,
+    left_sorted: bool,
+    right_sorted: bool,
+    memory_limit: usize,
+) -> f64 {
+    let mut cost = (left.get_no_tuples() + right.get_no_tuples()) as f64 * RECORD_CPU_COST;
+
+    if !left_sorted {
+        cost += sort_cost(left, memory_limit);
+    }
+    if !right_sorted {
+        cost += sort_cost(right, memory_limit);
+    }
+
+    cost
+}
+
+fn distincts(schema: &Schema, att: i32) -> f64 {
+    match schema.get_atts()[att as usize].no_distinct {
+        0 => DEFAULT_DISTINCTS,
+        no_distinct => no_distinct as f64,
+    }
+}
+
+fn literal_value(constants: &Record, index: i32) -> Option<f64> {
+    match constants.get_column(index as usize)? {
+        MappedAttrData::Integer(val) => Some(val as f64),
+        MappedAttrData::Float(val) => Some(val),
+        _ => None,
+    }
+}
+
+// Estimated fraction of the records for which `att op value` holds, from the statistics of the
+// attribute if it has been analyzed
+fn range_selectivity(schema: &Schema, att: i32, op: CompOp, value: Option<f64>, equal: f64) -> f64 {
+    let statistics = schema.get_atts()[att as usize].statistics.as_ref();
+    let (Some(statistics), Some(value)) = (statistics, value) else {
+        return RANGE_SELECTIVITY;
+    };
+
+    let below = statistics.fraction_below(value);
+    let selectivity = match op {
+        CompOp::Less => below,
+        CompOp::LessEqual => below + equal,
+        CompOp::Greater => 1.0 - below - equal,
+        CompOp::GreaterEqual => 1.0 - below,
+        CompOp::Equal | CompOp::NotEqual => unreachable!(),
+    };
+
+    selectivity.clamp(0.0, 1.0)
+}
+
+// Estimated fraction of the records of `schema` that pass `comparison`
+fn comparison_selectivity(comparison: &Comparison, schema: &Schema, constants: &Record) -> f64 {
+    // the attribute, with the operator as if the attribute came first
+    let (att, op, value) = match (comparison.operand1, comparison.operand2) {
+        // comparing two literals is either always or never true, no way to tell here
+        (Target::Literal, Target::Literal) => return 1.0,
+        (Target::Literal, _) => (
+            comparison.which_att2,
+            comparison.op.reversed(),
+            literal_value(constants, comparison.which_att1),
+        ),
+        (_, Target::Literal) => (
+            comparison.which_att1,
+            comparison.op,
+            literal_value(constants, comparison.which_att2),
+        ),
+        _ => {
+            let distincts1 = distincts(schema, comparison.which_att1);
+            let distincts2 = distincts(schema, comparison.which_att2);
+            let equal = 1.0 / f64::max(distincts1, distincts2);
+
+            return match comparison.op {
+                CompOp::Equal => equal,
+                CompOp::NotEqual => 1.0 - equal,
+                _ => RANGE_SELECTIVITY,
+            };
+        }
+    };
+
+    let equal = 1.0 / distincts(schema, att);
+    match op {
+        CompOp::Equal => equal,
+        CompOp::NotEqual => 1.0 - equal,
+        _ => range_selectivity(schema, att, op, value, equal),
+    }
+}
+
+// Estimated fraction of the records of `schema` that pass `cnf`. The disjunctions are assumed to
+// be independent, and a disjunction keeps the records that any of its comparisons keeps.
+pub fn selectivity(cnf: &Cnf, schema: &Schema, constants: &Record) -> f64 {
+    if cnf.is_false {
+        return 0.0;
+    }
+
+    cnf.and_list
+        .iter()
+        .map(|disjunction| {
+            disjunction
+                .or_list
+                .iter()
+                .map(|comparison| comparison_selectivity(comparison, schema, constants))
+                .fold(0.0, |either, selectivity| {
+                    either + selectivity - either * selectivity
+                })
+        })
+        .product()
+}
+
+// Updates the statistics of `schema` to those of the records that pass `cnf`
+pub fn estimate_select(schema: &mut Schema, cnf: &Cnf, constants: &Record) {
+    let selectivity = selectivity(cnf, schema, constants);
+    let no_tuples = (schema.get_no_tuples() as f64 * selectivity).ceil() as u64;
+
+    // attributes that have to equal a literal only have one value left
+    let fixed = cnf
+        .and_list
+        .iter()
+        .filter_map(|disjunction| match disjunction.or_list.as_slice() {
+            [comparison] if comparison.op == CompOp::Equal => {
+                match (comparison.operand1, comparison.operand2) {
+                    (Target::Left, Target::Literal) => Some(comparison.which_att1 as usize),
+                    (Target::Literal, Target::Left) => Some(comparison.which_att2 as usize),
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let atts = schema.get_atts().clone();
+    for (i, att) in atts.iter().enumerate() {
+        if fixed.contains(&i) {
+            schema.set_distincts(&att.name, 1);
+        } else if att.no_distinct > no_tuples {
+            schema.set_distincts(&att.name, no_tuples);
+        }
+    }
+
+    schema.set_no_tuples(no_tuples);
+}
+
+// Updates the statistics of `schema` for removing duplicate records, there can't be more records
+// left than combinations of distinct values
+pub fn estimate_dup_elim(schema: &mut Schema) {
+    let atts = schema.get_atts();
+    if atts.is_empty() || atts.iter().any(|att| att.no_distinct == 0) {
+        return;
+    }
+
+    let combinations = atts
+        .iter()
+        .map(|att| att.no_distinct as f64)
+        .product::<f64>();
+    if combinations < schema.get_no_tuples() as f64 {
+        schema.set_no_tuples(combinations as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_schema(atts: &[(&str, &str)], no_tuples: u64) -> Schema {
+        let names = atts
+            .iter()
+            .map(|(name, _)| name.to_string())
+            .collect::<Vec<_>>();
+        let types = atts
+            .iter()
+            .map(|(_, type_)| type_.to_string())
+            .collect::<Vec<_>>();
+        let distincts = vec![no_tuples; atts.len()];
+        Schema::new(&names, &types, &distincts, no_tuples, String::new())
+    }
+
+    #[test]
+    fn test_pages() {
+        // 3 * 8 bytes of data and 8 of overhead, so a page is limited by the record count
+        let narrow = create_schema(&[("a", "INTEGER"), ("b", "INTEGER"), ("c", "FLOAT")], 2500);
+        assert_eq!(record_size(&narrow), 32.0);
+        assert_eq!(pages(&narrow), 3.0);
+
+        // 200 strings of 29 bytes each
+        let atts = (0..200)
+            .map(|i| (format!("s{i}"), "STRING"))
+            .collect::<Vec<_>>();
+        let atts = atts
+            .iter()
+            .map(|(name, type_)| (name.as_str(), *type_))
+            .collect::<Vec<_>>();
+        let wide = create_schema(&atts, 2500);
+        assert_eq!(record_size(&wide), 5808.0);
+        assert_eq!(pages(&wide), (2500.0 / 22.0_f64).ceil());
+
+        assert_eq!(pages(&create_schema(&[("a", "INTEGER")], 0)), 0.0);
+    }
+
+    #[test]
+    fn test_join_costs() {
+        let small = create_schema(&[("a", "INTEGER")], 3);
+        let big = create_schema(&[("b", "INTEGER"), ("c", "STRING")], 1_000_000);
+
+        // a handful of records are cheaper to compare directly than to hash
+        let tiny = create_schema(&[("d", "INTEGER")], 1);
+        assert!(nested_loop_join_cost(&tiny, &small) < hash_join_cost(&tiny, &small, MEMORY_LIMIT));
+
+        assert!(hash_join_cost(&small, &big, MEMORY_LIMIT) < nested_loop_join_cost(&small, &big));
+
+        // building on the big side doesn't fit in memory and has to go through partitions
+        assert!(
+            hash_join_cost(&small, &big, MEMORY_LIMIT) < hash_join_cost(&big, &small, MEMORY_LIMIT)
+        );
+
+        let sorted = merge_join_cost(&small, &big, true, true, MEMORY_LIMIT);
+        let unsorted = merge_join_cost(&small, &big, true, false, MEMORY_LIMIT);
+        assert_eq!(sorted, hash_join_cost(&small, &big, MEMORY_LIMIT));
+        assert!(unsorted > sorted + 2.0 * pages(&big));
+    }
+
+    fn comparison(att1: (Target, i32), op: CompOp, att2: (Target, i32)) -> Comparison {
+        Comparison {
+            operand1: att1.0,
+            which_att1: att1.1,
+            operand2: att2.0,
+            which_att2: att2.1,
+            att_type: Type::Integer,
+            op,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-12, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn test_selectivity() {
+        let mut schema = create_schema(
+            &[("a", "INTEGER"), ("b", "INTEGER"), ("c", "INTEGER")],
+            1000,
+        );
+        schema.set_distincts("a", 50);
+        schema.set_distincts("b", 4);
+        schema.set_distincts("c", 0);
+
+        let a_is_1: Cnf = comparison((Target::Left, 0), CompOp::Equal, (Target::Literal, 0)).into();
+        let b_below_1: Cnf =
+            comparison((Target::Literal, 1), CompOp::Greater, (Target::Left, 1)).into();
+        let c_not_1: Cnf =
+            comparison((Target::Left, 2), CompOp::NotEqual, (Target::Literal, 2)).into();
+        let a_is_b: Cnf = comparison((Target::Left, 0), CompOp::Equal, (Target::Left, 1)).into();
+
+        let constants = Record::from(vec![MappedAttrData::Integer(1); 3]);
+
+        assert_close(selectivity(&a_is_1, &schema, &constants), 1.0 / 50.0);
+        assert_close(selectivity(&b_below_1, &schema, &constants), 1.0 / 3.0);
+        assert_close(
+            selectivity(&c_not_1, &schema, &constants),
+            1.0 - 1.0 / DEFAULT_DISTINCTS,
+        );
+        assert_close(selectivity(&a_is_b, &schema, &constants), 1.0 / 50.0);
+
+        let mut and = a_is_1.clone();
+        and *= b_below_1.clone();
+        assert_close(selectivity(&and, &schema, &constants), 1.0 / 150.0);
+
+        let mut or = a_is_1.clone();
+        or += b_below_1;
+        let expected = 1.0 - (1.0 - 1.0 / 50.0) * (1.0 - 1.0 / 3.0);
+        assert_close(selectivity(&or, &schema, &constants), expected);
+
+        assert_close(selectivity(&Cnf::new(), &schema, &constants), 1.0);
+        assert_close(
+            selectivity(&Cnf::new().negation(), &schema, &constants),
+            0.0,
+        );
+
+        estimate_select(&mut schema, &a_is_1, &constants);
+        assert_eq!(schema.get_no_tuples(), 20);
+        assert_eq!(schema.get_distincts("a"), Some(1));
+        assert_eq!(schema.get_distincts("b"), Some(4));
+
+        estimate_dup_elim(&mut schema);
+        assert_eq!(schema.get_no_tuples(), 20);
+        schema.project(&[0, 1]);
+        estimate_dup_elim(&mut schema);
+        assert_eq!(schema.get_no_tuples(), 4);
+    }
+
+    #[test]
+    fn test_range_selectivity() {
+        let mut schema = create_schema(&[("a", "FLOAT"), ("b", "FLOAT")], 1000);
+        schema.set_distincts("a", 100);
+        schema.set_distincts("b", 100);
+
+        // a is spread evenly over [0, 0.1], most values of b are below 0.02
+        let a = Statistics {
+            min: 0.0,
+            max: 0.1,
+            histogram: Vec::new(),
+        };
+        let b = Statistics {
+            min: 0.0,
+            max: 0.1,
+            histogram: vec![0.0, 0.005, 0.01, 0.02, 0.1],
+        };
+        schema.set_statistics("a", Some(a.clone()));
+        schema.set_statistics("b", Some(b.clone()));
+
+        assert_close(a.fraction_below(0.07), 0.7);
+        assert_close(b.fraction_below(0.015), 0.625);
+        assert_close(b.fraction_below(0.06), 0.875);
+        assert_eq!(b.fraction_below(-1.0), 0.0);
+        assert_eq!(b.fraction_below(1.0), 1.0);
+
+        let constants = Record::from(vec![
+            MappedAttrData::Float(0.07),
+            MappedAttrData::Float(0.015),
+        ]);
+
+        let a_below: Cnf = comparison((Target::Left, 0), CompOp::Less, (Target::Literal, 0)).into();
+        let a_above: Cnf =
+            comparison((Target::Left, 0), CompOp::Greater, (Target::Literal, 0)).into();
+        let b_below: Cnf =
+            comparison((Target::Literal, 1), CompOp::Greater, (Target::Left, 1)).into();
+        let b_at_least: Cnf = comparison(
+            (Target::Left, 1),
+            CompOp::GreaterEqual,
+            (Target::Literal, 1),
+        )
+        .into();
+
+        assert_close(selectivity(&a_below, &schema, &constants), 0.7);
+        assert_close(
+            selectivity(&a_above, &schema, &constants),
+            0.3 - 1.0 / 100.0,
+        );
+        assert_close(selectivity(&b_below, &schema, &constants), 0.625);
+        assert_close(selectivity(&b_at_least, &schema, &constants), 0.375);
+
+        // literals outside of the range of values keep nothing
+        let outside = Record::from(vec![MappedAttrData::Float(-1.0)]);
+        assert_close(selectivity(&a_below, &schema, &outside), 0.0);
+
+        // without statistics it's back to guessing
+        schema.set_statistics("a", None);
+        assert_close(
+            selectivity(&a_below, &schema, &constants),
+            RANGE_SELECTIVITY,
+        );
+
+        estimate_select(&mut schema, &b_below, &constants);
+        assert_eq!(schema.get_no_tuples(), 625);
+    }
+}
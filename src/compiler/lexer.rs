@@ -12,18 +12,32 @@ pub enum Token {
     Where,
     #[regex("(?i)SUM")]
     Sum,
+    #[regex("(?i)COUNT")]
+    Count,
+    #[regex("(?i)AVG")]
+    Avg,
+    #[regex("(?i)MIN")]
+    Min,
+    #[regex("(?i)MAX")]
+    Max,
     #[regex("(?i)GROUP")]
     Group,
     #[regex("(?i)ORDER")]
     Order,
     #[regex("(?i)BY")]
     By,
+    #[regex("(?i)HAVING")]
+    Having,
     #[regex("(?i)ASC")]
     Asc,
     #[regex("(?i)DESC")]
     Desc,
     #[regex("(?i)DISTINCT")]
     Distinct,
+    #[regex("(?i)LIMIT")]
+    Limit,
+    #[regex("(?i)OFFSET")]
+    Offset,
     #[regex("(?i)LEFT")]
     Left,
     #[regex("(?i)RIGHT")]
@@ -46,6 +60,10 @@ pub enum Token {
     Or,
     #[regex("(?i)NOT")]
     Not,
+    #[regex("(?i)ANALYZE")]
+    Analyze,
+    #[regex("(?i)EXPLAIN")]
+    Explain,
 
     // I added a bunch of these tokens for later, they are not currently used in the grammar
     #[regex("(?i)IS")]
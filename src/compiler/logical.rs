@@ -0,0 +1,582 @@
+use crate::*;
+
+use super::QueryCompiler;
+use super::ast::{self, Condition};
+use super::cost;
+
+// What a query computes, without deciding how. Every node carries the schema of its output along
+// with the estimates for it, so rewrites and costing can look at the plan before any data file gets
+// opened. `physical.rs` turns the plan into `RelOp`s.
+pub enum LogicalPlan {
+    Scan {
+        table: String,
+        schema: Schema,
+    },
+    // a relation without any records, like what's left of a selection that's never true
+    Empty {
+        schema: Schema,
+    },
+    // every combination of the records of the inputs, which is what a FROM list starts out as
+    // until the rewrite orders it into joins
+    Product {
+        inputs: Vec<LogicalPlan>,
+        schema: Schema,
+    },
+    Select {
+        predicate: Cnf,
+        constants: Record,
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    Project {
+        atts: Vec<i32>,
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    // the predicate only compares attributes, numbered like in `schema`: the left ones first and
+    // the right ones after them. `Cnf::into_join_predicate` gives the form the join operators use.
+    Join {
+        join_type: JoinType,
+        predicate: Cnf,
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    // the output has the grouping attributes followed by one attribute per aggregate, named after
+    // the aggregate
+    GroupBy {
+        grouping: Vec<String>,
+        aggregates: Vec<ast::Aggregate>,
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    DupElim {
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    // `limit` is how many records are needed at most, when there's a LIMIT right above
+    OrderBy {
+        atts: Vec<i32>,
+        ascending: bool,
+        limit: Option<usize>,
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+    Limit {
+        limit: usize,
+        offset: usize,
+        input: Box<LogicalPlan>,
+        schema: Schema,
+    },
+}
+
+impl LogicalPlan {
+    pub fn scan(table: &str, schema: Schema) -> Self {
+        LogicalPlan::Scan {
+            table: table.to_string(),
+            schema,
+        }
+    }
+
+    pub fn empty(mut schema: Schema) -> Self {
+        schema.set_no_tuples(0);
+        LogicalPlan::Empty { schema }
+    }
+
+    pub fn product(inputs: Vec<LogicalPlan>) -> Self {
+        let mut schema = inputs[0].schema().clone();
+        for input in &inputs[1..] {
+            schema.join_on(input.schema(), &[]);
+        }
+
+        LogicalPlan::Product { inputs, schema }
+    }
+
+    pub fn select(input: LogicalPlan, predicate: Cnf, constants: Record) -> Self {
+        let mut schema = input.schema().clone();
+        cost::estimate_select(&mut schema, &predicate, &constants);
+
+        LogicalPlan::Select {
+            predicate,
+            constants,
+            input: Box::new(input),
+            schema,
+        }
+    }
+
+    pub fn project(input: LogicalPlan, atts: Vec<i32>) -> Self {
+        let mut schema = input.schema().clone();
+        schema.project(&atts);
+
+        LogicalPlan::Project {
+            atts,
+            input: Box::new(input),
+            schema,
+        }
+    }
+
+    pub fn join(
+        join_type: JoinType,
+        predicate: Cnf,
+        left: LogicalPlan,
+        right: LogicalPlan,
+    ) -> Self {
+        let schema = join_schema(join_type, &predicate, left.schema(), right.schema());
+
+        LogicalPlan::Join {
+            join_type,
+            predicate,
+            left: Box::new(left),
+            right: Box::new(right),
+            schema,
+        }
+    }
+
+    // Fails if a grouping attribute or an aggregate doesn't fit the schema of the input. Aggregates
+    // that show up more than once only get computed once.
+    pub fn group_by(
+        input: LogicalPlan,
+        grouping: Vec<String>,
+        aggregates: Vec<ast::Aggregate>,
+    ) -> anyhow::Result<Self> {
+        let input_schema = input.schema();
+
+        let grouping_atts = grouping
+            .iter()
+            .map(|att| {
+                input_schema
+                    .index_of(att)
+                    .map(|i| i as i32)
+                    .ok_or_else(|| anyhow::anyhow!("Attribute '{}' not found in schema", att))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut names = Vec::new();
+        let mut types = Vec::new();
+        let mut distinct_aggregates = Vec::new();
+
+        for aggregate in aggregates {
+            let name = aggregate.to_string();
+            if names.contains(&name) {
+                continue;
+            }
+
+            let function = aggregate_function(&aggregate, input_schema)?;
+
+            types.push(function.output_type().to_string());
+            names.push(name);
+            distinct_aggregates.push(aggregate);
+        }
+
+        // there can't be more groups than records or than combinations of grouping values
+        let no_groups = grouping_atts
+            .iter()
+            .map(|&att| input_schema.get_atts()[att as usize].no_distinct.max(1))
+            .fold(1u64, |acc, distinct| acc.saturating_mul(distinct))
+            .min(input_schema.get_no_tuples().max(1));
+
+        let mut schema = input_schema.clone();
+        schema.project(&grouping_atts);
+        schema.append(&Schema::from_attributes(
+            &names,
+            &types,
+            &vec![no_groups; names.len()],
+        ));
+        schema.set_no_tuples(no_groups);
+
+        Ok(LogicalPlan::GroupBy {
+            grouping,
+            aggregates: distinct_aggregates,
+            input: Box::new(input),
+            schema,
+        })
+    }
+
+    pub fn dup_elim(input: LogicalPlan) -> Self {
+        let mut schema = input.schema().clone();
+        cost::estimate_dup_elim(&mut schema);
+
+        LogicalPlan::DupElim {
+            input: Box::new(input),
+            schema,
+        }
+    }
+
+    pub fn order_by(
+        input: LogicalPlan,
+        atts: Vec<i32>,
+        ascending: bool,
+        limit: Option<usize>,
+    ) -> Self {
+        let schema = input.schema().clone();
+
+        LogicalPlan::OrderBy {
+            atts,
+            ascending,
+            limit,
+            input: Box::new(input),
+            schema,
+        }
+    }
+
+    pub fn limit(input: LogicalPlan, limit: usize, offset: usize) -> Self {
+        let mut schema = input.schema().clone();
+        let no_tuples = schema.get_no_tuples().saturating_sub(offset as u64);
+        schema.set_no_tuples(no_tuples.min(limit as u64));
+
+        LogicalPlan::Limit {
+            limit,
+            offset,
+            input: Box::new(input),
+            schema,
+        }
+    }
+
+    pub fn schema(&self) -> &Schema {
+        match self {
+            LogicalPlan::Scan { schema, .. }
+            | LogicalPlan::Empty { schema }
+            | LogicalPlan::Product { schema, .. }
+            | LogicalPlan::Select { schema, .. }
+            | LogicalPlan::Project { schema, .. }
+            | LogicalPlan::Join { schema, .. }
+            | LogicalPlan::GroupBy { schema, .. }
+            | LogicalPlan::DupElim { schema, .. }
+            | LogicalPlan::OrderBy { schema, .. }
+            | LogicalPlan::Limit { schema, .. } => schema,
+        }
+    }
+
+    // Replaces every input of the node with `f` applied to it, and works out the schema again.
+    // The inputs have to keep their attributes in the same order, since the node refers to them
+    // by position.
+    pub fn map_inputs(
+        self,
+        mut f: impl FnMut(LogicalPlan) -> anyhow::Result<LogicalPlan>,
+    ) -> anyhow::Result<LogicalPlan> {
+        Ok(match self {
+            LogicalPlan::Scan { .. } | LogicalPlan::Empty { .. } => self,
+            LogicalPlan::Product { inputs, .. } => {
+                LogicalPlan::product(inputs.into_iter().map(f).collect::<anyhow::Result<_>>()?)
+            }
+            LogicalPlan::Select {
+                predicate,
+                constants,
+                input,
+                ..
+            } => LogicalPlan::select(f(*input)?, predicate, constants),
+            LogicalPlan::Project { atts, input, .. } => LogicalPlan::project(f(*input)?, atts),
+            LogicalPlan::Join {
+                join_type,
+                predicate,
+                left,
+                right,
+                ..
+            } => LogicalPlan::join(join_type, predicate, f(*left)?, f(*right)?),
+            LogicalPlan::GroupBy {
+                grouping,
+                aggregates,
+                input,
+                ..
+            } => LogicalPlan::group_by(f(*input)?, grouping, aggregates)?,
+            LogicalPlan::DupElim { input, .. } => LogicalPlan::dup_elim(f(*input)?),
+            LogicalPlan::OrderBy {
+                atts,
+                ascending,
+                limit,
+                input,
+                ..
+            } => LogicalPlan::order_by(f(*input)?, atts, ascending, limit),
+            LogicalPlan::Limit {
+                limit,
+                offset,
+                input,
+                ..
+            } => LogicalPlan::limit(f(*input)?, limit, offset),
+        })
+    }
+}
+
+// The estimated schema of joining `left` and `right` on `predicate`, where outer joins keep at
+// least every record of the sides they preserve
+pub fn join_schema(join_type: JoinType, predicate: &Cnf, left: &Schema, right: &Schema) -> Schema {
+    let join_atts = predicate
+        .clone()
+        .into_join_predicate(left.get_num_atts())
+        .equi_join_atts();
+
+    let mut schema = left.clone();
+    schema.join_on(right, &join_atts);
+
+    let mut no_tuples = schema.get_no_tuples();
+    if join_type.preserves_left() {
+        no_tuples = no_tuples.max(left.get_no_tuples());
+    }
+    if join_type.preserves_right() {
+        no_tuples = no_tuples.max(right.get_no_tuples());
+    }
+    schema.set_no_tuples(no_tuples);
+
+    schema
+}
+
+// The function computing `aggregate` over records of `schema`
+pub fn aggregate_function(
+    aggregate: &ast::Aggregate,
+    schema: &Schema,
+) -> anyhow::Result<AggregateFunction> {
+    let input = match aggregate.expr() {
+        None => AggregateInput::Star,
+        Some(ArithExpr::Load(att)) => {
+            let index = schema
+                .index_of(att)
+                .ok_or_else(|| anyhow::anyhow!("Attribute '{}' not found in schema", att))?;
+            AggregateInput::Attribute(index, schema.get_atts()[index].type_)
+        }
+        Some(expr) => {
+            for att in expr.attributes() {
+                match schema.find_type(att) {
+                    Some(Type::Integer | Type::Float) => (),
+                    Some(type_) => anyhow::bail!(
+                        "Attribute '{}' of type {} can't be used in arithmetic in {}",
+                        att,
+                        type_,
+                        aggregate
+                    ),
+                    None => anyhow::bail!("Attribute '{}' not found in schema", att),
+                }
+            }
+
+            AggregateInput::Function(Function::new(expr, schema))
+        }
+    };
+
+    AggregateFunction::new(aggregate.kind(), input)
+        .map_err(|error| anyhow::anyhow!("{} in {}", error, aggregate))
+}
+
+impl QueryCompiler<'_> {
+    // Resolves the names in the query against the catalog and builds the plan for it as written
+    pub(super) fn plan_ast(&self, query: ast::Query) -> anyhow::Result<LogicalPlan> {
+        match query {
+            ast::Query::Select {
+                atts,
+                from,
+                r#where,
+                distinct,
+            } => self.plan_select(atts, *from, r#where, distinct, None, None),
+            // the grammar puts the GROUP BY around the SELECT it belongs to
+            ast::Query::GroupBy { atts, having, from } => match *from {
+                ast::Query::Select {
+                    atts: select_atts,
+                    from,
+                    r#where,
+                    distinct,
+                } => self.plan_select(select_atts, *from, r#where, distinct, Some(atts), having),
+                // without a SELECT only the grouping attributes are kept
+                from => {
+                    let select_atts = ast::SelectAtts::Atts(
+                        atts.atts
+                            .iter()
+                            .cloned()
+                            .map(ast::SelectArg::Name)
+                            .collect(),
+                    );
+                    self.plan_select(select_atts, from, None, false, Some(atts), having)
+                }
+            },
+            ast::Query::OrderBy { asc, atts, from } => {
+                let input = self.plan_ast(*from)?;
+                let atts = atts
+                    .atts
+                    .iter()
+                    .filter_map(|s| input.schema().index_of(s))
+                    .map(|i| i as i32)
+                    .collect();
+
+                Ok(LogicalPlan::order_by(input, atts, asc, None))
+            }
+            ast::Query::Limit {
+                limit,
+                offset,
+                from,
+            } => Ok(LogicalPlan::limit(self.plan_ast(*from)?, limit, offset)),
+            ast::Query::Join {
+                join_type,
+                left,
+                right,
+                on,
+            } => self.plan_join(join_type, *left, *right, on),
+            ast::Query::Scan { table_names } => self.plan_scan(&table_names),
+        }
+    }
+
+    fn plan_scan(&self, table_names: &[String]) -> anyhow::Result<LogicalPlan> {
+        let mut scans = table_names
+            .iter()
+            .map(|table_name| {
+                let schema = self
+                    .catalog
+                    .get_schema(table_name)
+                    .ok_or_else(|| anyhow::anyhow!("Table '{}' not found in catalog", table_name))?
+                    .clone();
+
+                self.catalog.get_data_file(table_name).ok_or_else(|| {
+                    anyhow::anyhow!("Data file for table '{}' not found in catalog", table_name)
+                })?;
+
+                Ok(LogicalPlan::scan(table_name, schema))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if scans.len() == 1 {
+            return Ok(scans.pop().unwrap());
+        }
+
+        let mut schema = Schema::default();
+        for scan in &scans {
+            if !schema.append(scan.schema()) {
+                anyhow::bail!("Tables in the FROM list share an attribute name");
+            }
+        }
+
+        Ok(LogicalPlan::product(scans))
+    }
+
+    fn plan_select(
+        &self,
+        atts: ast::SelectAtts,
+        from: ast::Query,
+        r#where: Option<Condition>,
+        distinct: bool,
+        group_by: Option<ast::GroupByAtts>,
+        having: Option<Condition>,
+    ) -> anyhow::Result<LogicalPlan> {
+        let mut plan = self.plan_ast(from)?;
+
+        if let Some(r#where) = r#where {
+            let (predicate, constants) = self.compile_condition(&r#where, plan.schema())?;
+            plan = LogicalPlan::select(plan, predicate, constants);
+        }
+
+        let mut aggregates = match &atts {
+            ast::SelectAtts::Atts(atts) => atts
+                .iter()
+                .filter_map(|att| match att {
+                    ast::SelectArg::Aggregate(aggregate) => Some(aggregate.clone()),
+                    _ => None,
+                })
+                .collect(),
+            ast::SelectAtts::Star => Vec::new(),
+        };
+
+        // aggregates only used by the HAVING clause still have to be computed by the group by,
+        // the projection below drops them again
+        if let Some(having) = &having {
+            aggregates.extend(having.aggregates().into_iter().cloned());
+        }
+
+        let grouped = group_by.is_some() || !aggregates.is_empty();
+        if grouped {
+            let grouping = group_by.map(|group_by| group_by.atts).unwrap_or_default();
+            plan = LogicalPlan::group_by(plan, grouping, aggregates)?;
+        }
+
+        if let Some(having) = having {
+            let (predicate, constants) = self.compile_condition(&having, plan.schema())?;
+            plan = LogicalPlan::select(plan, predicate, constants);
+        }
+
+        if let ast::SelectAtts::Atts(atts) = atts {
+            let schema = plan.schema();
+            let atts_to_keep = atts
+                .iter()
+                .map(|att| match att {
+                    ast::SelectArg::Name(att) => {
+                        schema.index_of(att).map(|i| i as i32).ok_or_else(|| {
+                            if grouped {
+                                anyhow::anyhow!(
+                                    "Attribute '{}' must appear in the GROUP BY clause",
+                                    att
+                                )
+                            } else {
+                                anyhow::anyhow!("Attribute '{}' not found in schema", att)
+                            }
+                        })
+                    }
+                    // the group by already computed the aggregate under this name
+                    ast::SelectArg::Aggregate(aggregate) => {
+                        Ok(schema.index_of(&aggregate.to_string()).unwrap() as i32)
+                    }
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+
+            // checks whether or not the projection is the identity operation, in which
+            // case we can ignore it
+            if atts_to_keep != (0..schema.get_num_atts() as i32).collect::<Vec<_>>() {
+                plan = LogicalPlan::project(plan, atts_to_keep);
+            }
+        }
+
+        if distinct {
+            plan = LogicalPlan::dup_elim(plan);
+        }
+
+        Ok(plan)
+    }
+
+    fn plan_join(
+        &self,
+        join_type: JoinType,
+        left: ast::Query,
+        right: ast::Query,
+        on: Option<Condition>,
+    ) -> anyhow::Result<LogicalPlan> {
+        let mut left = self.plan_ast(left)?;
+        let mut right = self.plan_ast(right)?;
+
+        let mut schema = left.schema().clone();
+        if !schema.append(right.schema()) {
+            anyhow::bail!("Both sides of the join share an attribute name");
+        }
+
+        let (predicate, constants) = match on {
+            Some(on) => self.compile_condition(&on, &schema)?,
+            None => (Cnf::new(), Record::new()),
+        };
+
+        // the join operators only compare attributes of the two records, so anything involving a
+        // literal gets filtered after an inner join instead
+        let (mut filter, predicate) = predicate.partition_literals();
+
+        // filtering after an outer join would drop the padded records, but filtering the input
+        // that gets padded before the join is equivalent
+        if join_type != JoinType::Inner && !filter.is_empty() {
+            let padded = match join_type {
+                JoinType::LeftOuter => &mut right,
+                JoinType::RightOuter => &mut left,
+                _ => anyhow::bail!(
+                    "Conditions on literals are not supported in the ON clause of a FULL OUTER JOIN"
+                ),
+            };
+
+            let pushed = filter.project_to_schema(&schema, padded.schema());
+            if pushed.and_list.len() != filter.and_list.len() {
+                anyhow::bail!(
+                    "Conditions on literals in the ON clause of an outer join may only reference the padded side"
+                );
+            }
+
+            let input = std::mem::replace(padded, LogicalPlan::scan("", Schema::default()));
+            *padded = LogicalPlan::select(input, pushed, constants.clone());
+            filter = Cnf::new();
+        }
+
+        let mut plan = LogicalPlan::join(join_type, predicate, left, right);
+        if !filter.is_empty() {
+            plan = LogicalPlan::select(plan, filter, constants);
+        }
+
+        Ok(plan)
+    }
+}
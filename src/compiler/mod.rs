@@ -4,7 +4,12 @@ use lalrpop_util::*;
 mod ast;
 use ast::*;
 
+mod cost;
 mod lexer;
+mod logical;
+mod physical;
+mod rewrite;
+mod rules;
 
 lalrpop_mod!(
     #[allow(unused)]
@@ -16,11 +21,18 @@ use lexer::*;
 
 pub struct QueryCompiler<'a> {
     catalog: &'a Catalog,
+    // memory budget in bytes for the operators that can spill to disk
+    memory_limit: usize,
+    // whether join ordering also considers plans that join the results of two joins
+    bushy_plans: bool,
+    // the rewrites tried on every plan before joins get ordered
+    rules: Vec<Box<dyn rules::Rule>>,
+    // whether every operator gets wrapped with its estimates so the plan can be explained
+    explain: bool,
 }
 
-fn parse(query: &str) -> anyhow::Result<ast::Query> {
+fn tokenize(query: &str) -> anyhow::Result<Vec<(usize, Token, usize)>> {
     let mut lexer = logos::Lexer::new(query);
-    let parser = grammar::QueryParser::new();
 
     let tokens: Vec<_> = std::iter::from_fn(move || {
         let next = lexer.next()?;
@@ -39,190 +51,84 @@ fn parse(query: &str) -> anyhow::Result<ast::Query> {
         anyhow::bail!("Lexing errors at positions: {:?}", errors);
     }
 
-    let tokens: Vec<_> = tokens
+    Ok(tokens
         .into_iter()
         .map(|(start, tok, end)| (start, tok.unwrap(), end))
-        .collect();
+        .collect())
+}
 
-    Ok(parser.parse(tokens)?)
+fn parse(query: &str) -> anyhow::Result<ast::Query> {
+    let parser = grammar::QueryParser::new();
+    Ok(parser.parse(tokenize(query)?)?)
 }
 
-impl<'a> QueryCompiler<'a> {
-    pub fn new(catalog: &'a Catalog) -> Self {
-        Self { catalog }
-    }
-
-    // Assumes left-deep join trees
-    fn compute_join_cost(&self, combo: &[usize], cnf: &Option<(Cnf, Record, Schema)>, scans: &[(Schema, RelOp)]) -> usize {
-        let mut schema = scans[combo[0]].0.clone();
-        let mut cost = 0.0;
-
-        for i in 1..combo.len() {
-            let next_schema = &scans[combo[i]].0;
-
-            let cur_atts = schema.get_num_atts();
-            let next_atts = next_schema.get_num_atts();
-
-            let estimated_cost = cur_atts * next_atts;
-            let mut estimated_cost = estimated_cost as f64;
-
-            let old_schema = schema.clone();
-            schema.append(&scans[combo[i]].0);
- 
-            let cnf = match cnf {
-                Some(cnf) => cnf.0.project_to_schema(&cnf.2, &schema),
-                None => Cnf::new(),
-            };// Cnf::extract_cnf(&schema, next_schema);
-
-            for comparison in cnf.comparisons() {
-                let left_distincts = match comparison.operand1 {
-                    Target::Left => old_schema
-                        .get_distincts(&schema.get_atts()[comparison.which_att1 as usize].name)
-                        .unwrap_or(1) as f64,
-                    Target::Right => next_schema
-                        .get_distincts(&schema.get_atts()[comparison.which_att1 as usize].name)
-                        .unwrap_or(1) as f64,
-                    Target::Literal => 1.0,
-                };
-                let right_distincts = match comparison.operand2 {
-                    Target::Left => old_schema
-                        .get_distincts(&schema.get_atts()[comparison.which_att2 as usize].name)
-                        .unwrap_or(1) as f64,
-                    Target::Right => next_schema
-                        .get_distincts(&schema.get_atts()[comparison.which_att2 as usize].name)
-                        .unwrap_or(1) as f64,
-                    Target::Literal => 1.0,
-                };
+fn parse_statement(statement: &str) -> anyhow::Result<ast::Statement> {
+    let parser = grammar::StatementParser::new();
+    Ok(parser.parse(tokenize(statement)?)?)
+}
 
+// What running a single statement gives back. There's one per statement, so the size of the
+// execution tree doesn't matter.
+#[allow(clippy::large_enum_variant)]
+pub enum StatementResult {
+    // an execution tree for the caller to run
+    Query(QueryExecutionTree),
+    // the plan of an EXPLAIN, with the runtime statistics for EXPLAIN ANALYZE
+    Explain(String),
+    // statements that only update the catalog, like ANALYZE
+    Done,
+}
 
-                let max_distincts = f64::max(left_distincts, right_distincts);
-                if max_distincts != 0.0 {
-                    estimated_cost /= max_distincts;
+// Runs a single statement. Queries get compiled into an execution tree for the caller to run,
+// everything else is carried out right away.
+pub fn execute_statement(
+    catalog: &mut Catalog,
+    statement: &str,
+) -> anyhow::Result<StatementResult> {
+    match parse_statement(statement)? {
+        ast::Statement::Query(query) => QueryCompiler::new(catalog)
+            .compile_query(query)
+            .map(StatementResult::Query),
+        ast::Statement::Explain { query, analyze } => {
+            let mut compiler = QueryCompiler::new(catalog);
+            compiler.explain = true;
+
+            let (_schema, root) = compiler.compile_ast(query)?;
+            let mut tree = QueryExecutionTree { root };
+            if analyze {
+                // the records themselves are thrown away, only the statistics matter
+                tree.by_ref().for_each(drop);
+                if let Some(error) = tree.take_error() {
+                    return Err(error);
                 }
             }
 
-            cost += estimated_cost;
+            Ok(StatementResult::Explain(tree.as_string()))
         }
-
-        // using usize instead of f64 here because it impls Ord
-        cost as usize
-    }
-
-    fn choose_join(
-        &self,
-        predicate: Cnf,
-        left: RelOp,
-        right: RelOp,
-        left_schema: &Schema,
-        right_schema: &Schema,
-    ) -> RelOp {
-        // TODO: Implement the actual join choice logic based on the schemas and estimated costs
-
-        RelOp::NestedLoopJoin(NestedLoopJoin {
-            predicate,
-
-            records: Vec::new(),
-
-            left_producer: Box::new(left),
-            right_producer: Box::new(right),
-        })
-    }
-
-    fn dynamic_scan_order(&self, cnf: Option<(Cnf, Record, Schema)>, scans: Vec<(Schema, RelOp)>) -> anyhow::Result<(Schema, RelOp)> {
-        fn combinations<T: Clone>(items: Vec<T>) -> Vec<Vec<T>> {
-            if items.is_empty() {
-                return vec![vec![]];
-            }
-
-            let mut result = Vec::new();
-
-            for i in 0..items.len() {
-                let mut remaining = items.clone();
-                let item = remaining.remove(i);
-
-                for mut combo in combinations(remaining) {
-                    combo.push(item.clone());
-                    result.push(combo);
-                }
-            }
-
-            result
+        ast::Statement::Analyze { table } => {
+            catalog.analyze(&table)?;
+            Ok(StatementResult::Done)
         }
+    }
+}
 
-        let (combo, _) = combinations((0..scans.len()).collect())
-            .into_iter()
-            .map(|combo| {
-                let join_cost = self.compute_join_cost(&combo, &cnf, &scans);
-                (combo, join_cost)
-            })
-            .max_by_key(|(_, cost)| *cost)
-            .ok_or_else(|| anyhow::anyhow!("No scan combinations found"))?;
-
-        let mut scans: Vec<_> = scans.into_iter().map(Some).collect();
-
-        let (mut schema, mut relop) = scans[combo[0]].take().unwrap();
-
-        for i in 1..combo.len() {
-            let next_scan = scans[combo[i]].take().unwrap();
-            let next_schema = next_scan.0;
-            let next_relop = next_scan.1;
-
-            let old_schema = schema.clone();
-
-            schema.append(&next_schema);
-
-            let cnf = match cnf {
-                Some(ref cnf) => cnf.0.project_to_schema(&cnf.2, &schema),
-                None => Cnf::new(),
-            };
-
-            relop = self.choose_join(cnf, relop, next_relop, &schema, &next_schema);
+impl<'a> QueryCompiler<'a> {
+    pub fn new(catalog: &'a Catalog) -> Self {
+        Self {
+            catalog,
+            memory_limit: MEMORY_LIMIT,
+            bushy_plans: false,
+            rules: rules::default_rules(),
+            explain: false,
         }
-
-        Ok((schema, relop))
     }
 
-    fn greedy_scan_order(&self, cnf: Option<(Cnf, Record, Schema)>, scans: Vec<(Schema, RelOp)>) -> anyhow::Result<(Schema, RelOp)> {
-        // TODO: Actually implement the greedy algorithm described in 16.6.6
-        self.dynamic_scan_order(cnf, scans)
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        self.memory_limit = memory_limit;
     }
 
-    fn optimal_scan_relop(&self, cnf: Option<(Cnf, Record, Schema)>, table_names: &[String]) -> anyhow::Result<(Schema, RelOp)> {
-        let scans = table_names
-            .iter()
-            .map(|table_name| {
-                let schema = self
-                    .catalog
-                    .get_schema(&table_name)
-                    .ok_or_else(|| anyhow::anyhow!("Table '{}' not found in catalog", table_name))?
-                    .clone();
-
-                let path = self.catalog.get_data_file(&table_name).ok_or_else(|| {
-                    anyhow::anyhow!("Data file for table '{}' not found in catalog", table_name)
-                })?;
-
-                if path == "" {
-                    Ok((schema, RelOp::EmptyTableScan))
-                } else {
-                    let mut file = DBFile::new();
-                    if let Err(e) = file.open(&path) {
-                        // TODO: Make it actually fail, for now we just print the error and
-                        // continue with an empty scan as we just want the query plan to be
-                        // generated
-                        println!("{e}");
-                    }
-                    let scan = RelOp::Scan(Scan { file });
-
-                    Ok((schema, scan))
-                }
-            })
-            .collect::<anyhow::Result<Vec<_>>>()?;
-
-        if scans.len() <= 4 {
-            self.dynamic_scan_order(cnf, scans)
-        } else {
-            self.greedy_scan_order(cnf, scans)
-        }
+    pub fn set_bushy_plans(&mut self, bushy_plans: bool) {
+        self.bushy_plans = bushy_plans;
     }
 
     fn compile_condition(
@@ -332,6 +238,26 @@ impl<'a> QueryCompiler<'a> {
                                 _ => anyhow::bail!("Unsupported arithmetic expression in condition"),
                             }
                         },
+                        // aggregates are attributes of the group by's output, named after the
+                        // aggregate
+                        ConditionExpr::Aggregate(aggregate) => {
+                            let name = aggregate.to_string();
+                            let att_index = schema.index_of(&name).ok_or_else(|| {
+                                anyhow::anyhow!("Aggregate {} can only be used in a HAVING clause", name)
+                            })?;
+
+                            let att_type_in_schema = schema.get_atts()[att_index].type_;
+
+                            if let Some(att_type) = att_type {
+                                if att_type != att_type_in_schema {
+                                    anyhow::bail!("Type mismatch in condition: expected {:?}, found {} of type {:?}", att_type, name, att_type_in_schema);
+                                }
+                            } else {
+                                att_type = Some(att_type_in_schema);
+                            }
+
+                            (Target::Left, att_index as i32)
+                        },
                     })
                 }
 
@@ -354,172 +280,1097 @@ impl<'a> QueryCompiler<'a> {
         }
     }
 
+    // Binds the query to the catalog, rewrites the plan and picks the operators for it. Also gives
+    // the estimated schema of the result.
     fn compile_ast(&self, query: ast::Query) -> anyhow::Result<(Schema, RelOp)> {
-        match query {
-            ast::Query::Select {
-                atts,
-                from,
-                r#where,
-                distinct,
-            } => {
-                let (mut schema, mut producer) = if let Some(r#where) = r#where {
-                    // TODO: estimate effect on no_tuples and update schema accordingly
-                    if matches!(*from, ast::Query::Scan { .. }) {
-                        let ast::Query::Scan { table_names } = *from else { unreachable!() };
-                        let mut schema = table_names
-                            .iter()
-                            .filter_map(|table_name| self.catalog.get_schema(table_name))
-                            .cloned()
-                            .reduce(|mut acc, schema| {
-                                acc.append(&schema);
-                                acc
-                            })
-                            .ok_or_else(|| anyhow::anyhow!("No tables found in scan"))?;
-
-                        let (predicate, constants) = self.compile_condition(&r#where, &schema)?;
-                        // TODO: refactor to handle constants properly instead of just ignoring
-                        // them like this
-
-                        let (schema, producer) = self.optimal_scan_relop(Some((predicate, constants, schema)), &table_names)?;
-                        let (predicate, constants) = self.compile_condition(&r#where, &schema)?;
-
-                        let producer = RelOp::Select(Select {
-                            producer: Box::new(producer),
-                            predicate,
-                            constants,
-                        });
-
-                        (schema, producer)
-                    } else {
-                        let (schema, producer) = self.compile_ast(*from)?;
-                        let (predicate, constants) = self.compile_condition(&r#where, &schema)?;
-
-                        let producer = RelOp::Select(Select {
-                            producer: Box::new(producer),
-                            predicate,
-                            constants,
-                        });
-
-                        (schema, producer)
-                    }
-                } else {
-                    self.compile_ast(*from)?
-                };
+        let plan = self.plan_ast(query)?;
+        let plan = self.rewrite(plan)?;
+        let schema = plan.schema().clone();
 
-                if let ast::SelectAtts::Atts(atts) = atts {
-                    if atts
-                        .iter()
-                        .any(|att| matches!(att, ast::SelectArg::Aggregate(_)))
-                    {
-                        match producer {
-                            RelOp::GroupBy(ref mut groupby) => {
-                                // TODO: Add the aggregate functions into the GroupBy relop so that
-                                // they can be computed during grouping
-                            }
-                            _ => {
-                                // TODO: Add a GroupBy relop with an empty grouping to compute the
-                                // aggregates
-                            }
-                        }
-                    }
-                    let mut atts_to_keep = atts
-                        .iter()
-                        .filter_map(|att| match att {
-                            ast::SelectArg::Name(att) => Some(att),
-                            _ => None,
-                        })
-                        .map(|att| {
-                            schema.index_of(att).map(|i| i as i32).ok_or_else(|| {
-                                anyhow::anyhow!("Attribute '{:?}' not found in schema", att)
-                            })
-                        })
-                        .collect::<anyhow::Result<Vec<_>>>()?;
-
-                    // TODO: Add the part where you also keep the aggregates
-
-                    // checks whether or not the projection is the identity operation, in which
-                    // case we can ignore it
-                    if !(atts_to_keep == (0..schema.get_num_atts() as i32).collect::<Vec<_>>()) {
-                        schema.project(&atts_to_keep);
-
-                        producer = RelOp::Project(Project {
-                            producer: Box::new(producer),
-                            atts_to_keep,
-                        });
-                    }
-                }
+        Ok((schema, self.lower(plan)?))
+    }
 
-                if distinct {
-                    // TODO: estimate effect on no_tuples and update schema accordingly
-                    producer = RelOp::DupElim(DupElim {
-                        seen: std::collections::HashSet::new(),
-                        producer: Box::new(producer),
-                    });
-                }
+    pub fn compile(&self, query: &str) -> anyhow::Result<QueryExecutionTree> {
+        self.compile_query(parse(query)?)
+    }
+
+    fn compile_query(&self, query: ast::Query) -> anyhow::Result<QueryExecutionTree> {
+        let (_schema, relop) = self.compile_ast(query)?;
+        let relop = RelOp::WriteOut(WriteOut {
+            file: "output.tbl".to_string(),
+            producer: Box::new(relop),
+        });
 
-                Ok((schema, producer))
+        Ok(QueryExecutionTree { root: relop })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    struct Table<'a> {
+        name: &'a str,
+        atts: &'a [(&'a str, &'a str)],
+        rows: &'a [&'a str],
+    }
+
+    fn create_test_catalog(dir: &TempDir, tables: &[Table]) -> Catalog {
+        let mut sql = String::new();
+
+        for table in tables {
+            let text_path = dir.path().join(format!("{}.tbl", table.name));
+            let mut text_file = std::fs::File::create(&text_path).unwrap();
+            for row in table.rows {
+                writeln!(text_file, "{row}").unwrap();
             }
-            ast::Query::GroupBy { atts, from } => {
-                let (schema, producer) = self.compile_ast(*from)?;
-                let grouping = OrderMaker::from_atts(
-                    &schema,
-                    &atts
-                        .atts
-                        .iter()
-                        .filter_map(|s| schema.index_of(&s))
-                        .map(|i| i as i32)
-                        .collect::<Vec<_>>(),
-                );
-                let groupby = RelOp::GroupBy(GroupBy {
-                    grouping,
-                    current_group: Vec::new(),
-                    next_record: None,
-                    producer: Box::new(producer),
-                });
-
-                Ok((schema, groupby))
+
+            let names = table
+                .atts
+                .iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>();
+            let types = table
+                .atts
+                .iter()
+                .map(|(_, type_)| type_.to_string())
+                .collect::<Vec<_>>();
+            let distincts = vec![table.rows.len() as u64; names.len()];
+            let schema = Schema::from_attributes(&names, &types, &distincts);
+
+            let db_path = dir.path().join(format!("{}.dat", table.name));
+            let mut db_file = DBFile::new();
+            db_file.create(&db_path, FileType::Heap).unwrap();
+            db_file.load(&schema, text_path.to_str().unwrap()).unwrap();
+            db_file.close().unwrap();
+
+            sql.push_str(&format!(
+                "INSERT INTO Tables VALUES('{}', {}, '{}');\n",
+                table.name,
+                table.rows.len(),
+                db_path.display()
+            ));
+            for (pos, (name, type_)) in table.atts.iter().enumerate() {
+                sql.push_str(&format!(
+                    "INSERT INTO Attributes VALUES('{}', {pos}, '{name}', '{type_}', {});\n",
+                    table.name,
+                    table.rows.len()
+                ));
             }
-            ast::Query::OrderBy { asc, atts, from } => {
-                let (schema, producer) = self.compile_ast(*from)?;
-                let ordering = OrderMaker::from_atts(
-                    &schema,
-                    &atts
-                        .atts
-                        .iter()
-                        .filter_map(|s| schema.index_of(&s))
-                        .map(|i| i as i32)
-                        .collect::<Vec<_>>(),
+        }
+
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE Tables (name VARCHAR, num_tuples INT, file VARCHAR);
+            CREATE TABLE Attributes (table_name VARCHAR, position INT, name VARCHAR, type VARCHAR, num_distinct INT);
+        ",
+        )
+        .unwrap();
+        conn.execute_batch(&sql).unwrap();
+
+        Catalog::from_conn(conn).unwrap()
+    }
+
+    fn customers_and_orders(dir: &TempDir) -> Catalog {
+        create_test_catalog(
+            dir,
+            &[
+                Table {
+                    name: "customer",
+                    atts: &[("c_custkey", "INTEGER"), ("c_name", "STRING")],
+                    rows: &["1|Alice|", "2|Bob|", "3|Carol|"],
+                },
+                Table {
+                    name: "orders",
+                    atts: &[
+                        ("o_orderkey", "INTEGER"),
+                        ("o_custkey", "INTEGER"),
+                        ("o_totalprice", "FLOAT"),
+                    ],
+                    rows: &["10|1|100.0|", "11|1|250.5|", "12|2|75.25|", "13|4|10.0|"],
+                },
+            ],
+        )
+    }
+
+    fn run_query(catalog: &Catalog, query: &str) -> (Schema, Vec<String>) {
+        let compiler = QueryCompiler::new(catalog);
+        let (schema, mut relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+
+        let mut rows = relop
+            .by_ref()
+            .map(|record| {
+                String::from_utf8(record.to_bytes())
+                    .unwrap()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        if let Some(error) = relop.take_error() {
+            panic!("{query} failed: {error}");
+        }
+
+        (schema, rows)
+    }
+
+    #[test]
+    fn test_inner_join_on() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer INNER JOIN orders ON c_custkey = o_custkey",
+        );
+
+        assert_eq!(schema.get_num_atts(), 2);
+        assert_eq!(rows, vec!["Alice|10|", "Alice|11|", "Bob|12|"]);
+    }
+
+    #[test]
+    fn test_inner_join_on_with_literal() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT o_orderkey FROM customer INNER JOIN orders ON c_custkey = o_custkey AND o_totalprice > 90.0",
+        );
+
+        assert_eq!(rows, vec!["10|", "11|"]);
+    }
+
+    #[test]
+    fn test_left_outer_join() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer LEFT JOIN orders ON c_custkey = o_custkey",
+        );
+
+        assert_eq!(rows, vec!["Alice|10|", "Alice|11|", "Bob|12|", "Carol||"]);
+    }
+
+    #[test]
+    fn test_right_outer_join() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer RIGHT JOIN orders ON c_custkey = o_custkey",
+        );
+
+        assert_eq!(rows, vec!["Alice|10|", "Alice|11|", "Bob|12|", "|13|"]);
+    }
+
+    #[test]
+    fn test_full_outer_join() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer FULL OUTER JOIN orders ON c_custkey = o_custkey",
+        );
+
+        assert_eq!(
+            rows,
+            vec!["Alice|10|", "Alice|11|", "Bob|12|", "Carol||", "|13|"]
+        );
+    }
+
+    #[test]
+    fn test_left_outer_join_with_literal_on_padded_side() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        // the literal condition only restricts which orders match, every customer is kept
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer LEFT JOIN orders ON c_custkey = o_custkey AND o_totalprice > 200.0",
+        );
+
+        assert_eq!(rows, vec!["Alice|11|", "Bob||", "Carol||"]);
+    }
+
+    #[test]
+    fn test_join_choice() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (plan, rows) = run_query_ordered(
+            &catalog,
+            "SELECT c_name, o_orderkey FROM customer INNER JOIN orders ON c_custkey = o_custkey",
+        );
+        assert!(plan.contains("HashJoin"), "{plan}");
+        assert_eq!(rows.len(), 3);
+
+        // no way to hash or sort on an inequality
+        let query =
+            "SELECT c_name, o_orderkey FROM customer INNER JOIN orders ON c_custkey > o_custkey";
+        let (plan, _) = run_query_ordered(&catalog, query);
+        assert!(plan.contains("NestedLoopJoin"), "{plan}");
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(
+            rows,
+            vec!["Bob|10|", "Bob|11|", "Carol|10|", "Carol|11|", "Carol|12|"]
+        );
+    }
+
+    #[test]
+    fn test_merge_join_on_sorted_inputs() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        let compile = |query| compiler.compile_ast(parse(query).unwrap()).unwrap();
+
+        // c_custkey = o_custkey
+        let predicate: Cnf = Comparison {
+            operand1: Target::Left,
+            which_att1: 0,
+            operand2: Target::Right,
+            which_att2: 1,
+            att_type: Type::Integer,
+            op: CompOp::Equal,
+        }
+        .into();
+
+        // both inputs already come out sorted on the join keys, so merging them is cheapest
+        let (left_schema, left) = compile("SELECT * FROM customer ORDER BY c_custkey");
+        let (right_schema, right) = compile("SELECT * FROM orders ORDER BY o_custkey");
+        let join = compiler.choose_join(
+            predicate.clone(),
+            JoinType::Inner,
+            left,
+            right,
+            &left_schema,
+            &right_schema,
+        );
+
+        let RelOp::MergeJoin(merge_join) = &join else {
+            panic!("expected a merge join");
+        };
+        assert!(matches!(*merge_join.left_producer, RelOp::OrderBy(_)));
+        assert!(matches!(*merge_join.right_producer, RelOp::OrderBy(_)));
+        assert_eq!(join.count(), 3);
+
+        // sorting the orders first costs more than hashing them
+        let (left_schema, left) = compile("SELECT * FROM customer ORDER BY c_custkey");
+        let (right_schema, right) = compile("SELECT * FROM orders");
+        let join = compiler.choose_join(
+            predicate,
+            JoinType::Inner,
+            left,
+            right,
+            &left_schema,
+            &right_schema,
+        );
+        assert!(matches!(join, RelOp::HashJoin(_)));
+    }
+
+    // region <- nation <- supplier <- partsupp -> part, five tables so the joins get ordered
+    // greedily
+    fn suppliers_and_parts(dir: &TempDir) -> Catalog {
+        create_test_catalog(
+            dir,
+            &[
+                Table {
+                    name: "region",
+                    atts: &[("r_key", "INTEGER"), ("r_name", "STRING")],
+                    rows: &["1|Europe|", "2|Asia|"],
+                },
+                Table {
+                    name: "nation",
+                    atts: &[("n_key", "INTEGER"), ("n_regionkey", "INTEGER")],
+                    rows: &["10|1|", "11|1|", "12|2|", "13|3|"],
+                },
+                Table {
+                    name: "supplier",
+                    atts: &[("s_key", "INTEGER"), ("s_nationkey", "INTEGER")],
+                    rows: &[
+                        "100|10|", "101|11|", "102|12|", "103|12|", "104|13|", "105|99|",
+                    ],
+                },
+                Table {
+                    name: "partsupp",
+                    atts: &[("ps_suppkey", "INTEGER"), ("ps_partkey", "INTEGER")],
+                    rows: &[
+                        "100|1000|",
+                        "101|1001|",
+                        "102|1000|",
+                        "104|1002|",
+                        "103|1003|",
+                        "106|1000|",
+                    ],
+                },
+                Table {
+                    name: "part",
+                    atts: &[("p_key", "INTEGER"), ("p_name", "STRING")],
+                    rows: &[
+                        "1000|bolt|",
+                        "1001|nut|",
+                        "1002|screw|",
+                        "1003|gear|",
+                        "1004|cog|",
+                    ],
+                },
+            ],
+        )
+    }
+
+    fn join_predicates(relop: &RelOp) -> Vec<&Cnf> {
+        match relop {
+            RelOp::NestedLoopJoin(join) => [&join.predicate]
+                .into_iter()
+                .chain(join_predicates(&join.left_producer))
+                .chain(join_predicates(&join.right_producer))
+                .collect(),
+            RelOp::HashJoin(join) => [&join.predicate]
+                .into_iter()
+                .chain(join_predicates(&join.left_producer))
+                .chain(join_predicates(&join.right_producer))
+                .collect(),
+            RelOp::MergeJoin(join) => [&join.predicate]
+                .into_iter()
+                .chain(join_predicates(&join.left_producer))
+                .chain(join_predicates(&join.right_producer))
+                .collect(),
+            RelOp::Select(select) => join_predicates(&select.producer),
+            RelOp::Project(project) => join_predicates(&project.producer),
+            RelOp::OrderBy(order_by) => join_predicates(&order_by.producer),
+            _ => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_greedy_join_order() {
+        let dir = TempDir::new().unwrap();
+        let catalog = suppliers_and_parts(&dir);
+
+        // region and part are listed next to each other but have nothing to join on
+        let query = "SELECT r_name, p_name FROM region, part, supplier, nation, partsupp \
+            WHERE r_key = n_regionkey AND n_key = s_nationkey AND s_key = ps_suppkey AND ps_partkey = p_key";
+
+        let compiler = QueryCompiler::new(&catalog);
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+
+        let predicates = join_predicates(&relop);
+        assert_eq!(predicates.len(), 4);
+        for predicate in predicates {
+            assert!(
+                !predicate.equi_join_atts().is_empty(),
+                "cross product in the plan"
+            );
+        }
+
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(
+            rows,
+            vec!["Asia|bolt|", "Asia|gear|", "Europe|bolt|", "Europe|nut|"]
+        );
+    }
+
+    #[test]
+    fn test_dynamic_join_order() {
+        let dir = TempDir::new().unwrap();
+        let catalog = suppliers_and_parts(&dir);
+
+        // region and partsupp are listed next to each other but have nothing to join on
+        let query = "SELECT r_name, ps_partkey FROM region, partsupp, supplier, nation \
+            WHERE r_key = n_regionkey AND n_key = s_nationkey AND s_key = ps_suppkey";
+
+        for bushy_plans in [false, true] {
+            let mut compiler = QueryCompiler::new(&catalog);
+            compiler.set_bushy_plans(bushy_plans);
+            let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+
+            let predicates = join_predicates(&relop);
+            assert_eq!(predicates.len(), 3);
+            for predicate in predicates {
+                assert!(
+                    !predicate.equi_join_atts().is_empty(),
+                    "cross product in the plan"
                 );
-                let orderby = RelOp::OrderBy(OrderBy {
-                    producer: Box::new(producer),
-                    records: Vec::new(),
-                    ordering,
-                    ascending: asc,
-                });
-
-                Ok((schema, orderby))
             }
-            ast::Query::Join {
-                join_type,
-                left,
-                right,
-                on,
-            } => {
-                todo!()
+
+            let mut rows = relop
+                .map(|record| {
+                    String::from_utf8(record.to_bytes())
+                        .unwrap()
+                        .trim_end()
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            rows.sort();
+            assert_eq!(
+                rows,
+                vec!["Asia|1000|", "Asia|1003|", "Europe|1000|", "Europe|1001|"]
+            );
+        }
+    }
+
+    fn join_children(relop: &RelOp) -> Option<(&RelOp, &RelOp)> {
+        match relop {
+            RelOp::NestedLoopJoin(join) => Some((&join.left_producer, &join.right_producer)),
+            RelOp::HashJoin(join) => Some((&join.left_producer, &join.right_producer)),
+            RelOp::MergeJoin(join) => Some((&join.left_producer, &join.right_producer)),
+            RelOp::OrderBy(order_by) => join_children(&order_by.producer),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_bushy_plan() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let mut compiler = QueryCompiler::new(&catalog);
+
+        // a - b and c - d each join down to 10 records, while joining b with c first gives 100000
+        let scans = || {
+            let table = |atts: &[(&str, u64)], no_tuples| {
+                let names = atts
+                    .iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect::<Vec<_>>();
+                let types = vec!["INTEGER".to_string(); atts.len()];
+                let distincts = atts
+                    .iter()
+                    .map(|(_, distincts)| *distincts)
+                    .collect::<Vec<_>>();
+                let schema = Schema::new(&names, &types, &distincts, no_tuples, String::new());
+
+                logical::LogicalPlan::scan("", schema)
+            };
+
+            vec![
+                table(&[("a", 10)], 10),
+                table(&[("b_a", 1000), ("b_c", 10)], 1000),
+                table(&[("c_c", 10), ("c_d", 1000)], 1000),
+                table(&[("d", 10)], 10),
+            ]
+        };
+
+        let mut schema = Schema::default();
+        for scan in scans() {
+            schema.append(scan.schema());
+        }
+        let ast::Query::Select {
+            r#where: Some(condition),
+            ..
+        } = parse("SELECT * FROM t WHERE a = b_a AND b_c = c_c AND c_d = d").unwrap()
+        else {
+            panic!("expected a select with a where clause");
+        };
+        let (cnf, constants) = compiler.compile_condition(&condition, &schema).unwrap();
+
+        for bushy_plans in [false, true] {
+            compiler.set_bushy_plans(bushy_plans);
+            let plan = compiler
+                .dynamic_scan_order(
+                    Some((cnf.clone(), constants.clone(), schema.clone())),
+                    scans(),
+                )
+                .unwrap();
+
+            let is_join =
+                |plan: &logical::LogicalPlan| matches!(plan, logical::LogicalPlan::Join { .. });
+            let logical::LogicalPlan::Join { left, right, .. } = plan else {
+                panic!("expected a join at the top");
+            };
+            let is_bushy = is_join(&left) && is_join(&right);
+            assert_eq!(is_bushy, bushy_plans);
+        }
+    }
+
+    #[test]
+    fn test_predicate_pushdown() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        fn skip_projects(mut relop: &RelOp) -> &RelOp {
+            while let RelOp::Project(project) = relop {
+                relop = &project.producer;
+            }
+            relop
+        }
+
+        // whether the top of the plan, not counting projections, is a `Select` right above a join
+        let select_over_join = |query: &str| {
+            let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+            match skip_projects(&relop) {
+                RelOp::Select(select) => join_children(skip_projects(&select.producer)).is_some(),
+                _ => false,
             }
-            ast::Query::Scan { table_names } => self.optimal_scan_relop(None, &table_names),
+        };
+
+        // the filters on single tables end up right above their scans, and the join only gets
+        // the comparison across the two
+        let query = "SELECT c_name, o_orderkey FROM customer, orders \
+            WHERE c_custkey = o_custkey AND o_totalprice > 50.0 AND c_name = 'Alice'";
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        let tree = QueryExecutionTree { root: relop }.as_string();
+        assert_eq!(tree.matches("Select").count(), 2, "{tree}");
+        assert_eq!(
+            tree.matches("Select\n").count(),
+            tree.matches("└─Scan").count(),
+            "{tree}"
+        );
+        assert!(!select_over_join(query));
+
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        let predicates = join_predicates(&relop);
+        assert_eq!(predicates.len(), 1);
+        assert_eq!(predicates[0].and_list.len(), 1);
+
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(rows, vec!["Alice|10|", "Alice|11|"]);
+
+        // a comparison with a literal that needs both tables goes right above the join
+        let query = "SELECT c_name, o_orderkey FROM customer, orders \
+            WHERE c_custkey = o_custkey AND c_name = 'Bob' OR o_totalprice > 200.0";
+        assert!(select_over_join(query));
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(rows, vec!["Alice|11|", "Bob|11|", "Bob|12|", "Carol|11|"]);
+
+        // comparisons without any attributes stay at the top
+        let query = "SELECT c_name, o_orderkey FROM customer, orders \
+            WHERE c_custkey = o_custkey AND 1 = 2";
+        assert!(run_query(&catalog, query).1.is_empty());
+
+        // unless they get folded away, and a WHERE that's never true doesn't read anything
+        let mut compiler = QueryCompiler::new(&catalog);
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        assert!(matches!(skip_projects(&relop), RelOp::EmptyTableScan));
+
+        compiler.rules.clear();
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        match skip_projects(&relop) {
+            RelOp::Select(select) => {
+                assert!(join_children(skip_projects(&select.producer)).is_some())
+            }
+            _ => panic!("expected the comparison right above the join"),
         }
     }
 
-    pub fn compile(&self, query: &str) -> anyhow::Result<QueryExecutionTree> {
-        let ast = parse(query)?;
+    #[test]
+    fn test_projection_pushdown() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        // the width of the records coming out of every join and every leaf, usize::MAX for the ones
+        // that aren't projected
+        fn collect_widths(relop: &RelOp, width: Option<usize>, widths: &mut Vec<usize>) {
+            match relop {
+                RelOp::Project(project) => {
+                    let width = width.or(Some(project.atts_to_keep.len()));
+                    collect_widths(&project.producer, width, widths);
+                }
+                RelOp::Select(select) => collect_widths(&select.producer, width, widths),
+                RelOp::OrderBy(order_by) => collect_widths(&order_by.producer, width, widths),
+                _ => match join_children(relop) {
+                    Some((left, right)) => {
+                        widths.extend(width);
+                        collect_widths(left, None, widths);
+                        collect_widths(right, None, widths);
+                    }
+                    None => widths.push(width.unwrap_or(usize::MAX)),
+                },
+            }
+        }
 
-        let (_schema, relop) = self.compile_ast(ast)?;
-        let relop = RelOp::WriteOut(WriteOut {
-            file: "output.tbl".to_string(),
-            producer: Box::new(relop),
-        });
+        // only the keys of orders make it into the join, and only c_name out of it
+        let query = "SELECT c_name FROM customer, orders \
+            WHERE c_custkey = o_custkey AND o_totalprice > 50.0";
+        let (_, relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        let mut found = Vec::new();
+        collect_widths(&relop, None, &mut found);
+        found.sort();
+        assert_eq!(found, vec![1, 1, usize::MAX]);
+
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(rows, vec!["Alice|", "Alice|", "Bob|"]);
+
+        // nothing at all is needed for counting
+        let (_, rows) = run_query(&catalog, "SELECT COUNT(*) FROM customer, orders");
+        assert_eq!(rows, vec!["12|"]);
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT COUNT(*) FROM customer, orders WHERE c_custkey = o_custkey",
+        );
+        assert_eq!(rows, vec!["3|"]);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, SUM(o_totalprice) FROM customer, orders \
+                WHERE c_custkey = o_custkey GROUP BY c_name HAVING MAX(o_orderkey) > 11",
+        );
+        assert_eq!(rows, vec!["Bob|75.25|"]);
+    }
 
-        Ok(QueryExecutionTree { root: relop })
+    #[test]
+    fn test_cross_product() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(&catalog, "SELECT c_name, o_orderkey FROM customer, orders");
+        assert_eq!(rows.len(), 12);
+    }
+
+    #[test]
+    fn test_logical_plan() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        let names = |plan: &logical::LogicalPlan| {
+            plan.schema()
+                .get_atts()
+                .iter()
+                .map(|att| att.name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        // the FROM list stays a product until the rewrite orders it into joins
+        let query = "SELECT * FROM orders, customer WHERE c_custkey = o_custkey";
+        let plan = compiler.plan_ast(parse(query).unwrap()).unwrap();
+        let logical::LogicalPlan::Select { input, .. } = &plan else {
+            panic!("expected the WHERE clause at the top");
+        };
+        assert!(matches!(**input, logical::LogicalPlan::Product { .. }));
+
+        // whatever order the joins end up in, the attributes come out in the order of the query
+        let expected = names(&plan);
+        let plan = compiler.rewrite(plan).unwrap();
+        assert_eq!(names(&plan), expected);
+        assert_eq!(expected[0], "o_orderkey");
+
+        let (_, rows) = run_query(&catalog, query);
+        assert_eq!(
+            rows,
+            vec![
+                "10|1|100|1|Alice|",
+                "11|1|250.5|1|Alice|",
+                "12|2|75.25|2|Bob|"
+            ]
+        );
+
+        // the sort under a LIMIT only keeps as many records as the limit and offset need
+        let query = "SELECT o_orderkey FROM orders ORDER BY o_totalprice DESC LIMIT 2 OFFSET 1";
+        let plan = compiler
+            .rewrite(compiler.plan_ast(parse(query).unwrap()).unwrap())
+            .unwrap();
+        let mut node = &plan;
+        let limit = loop {
+            match node {
+                logical::LogicalPlan::OrderBy { limit, .. } => break *limit,
+                logical::LogicalPlan::Select { input, .. }
+                | logical::LogicalPlan::Project { input, .. }
+                | logical::LogicalPlan::Limit { input, .. } => node = input,
+                _ => break None,
+            }
+        };
+        assert_eq!(limit, Some(3));
+    }
+
+    #[test]
+    fn test_select_estimates() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, _) = run_query(&catalog, "SELECT * FROM orders WHERE o_custkey = 1");
+        assert_eq!(schema.get_no_tuples(), 1);
+
+        // the range leaves 2 of the 4 orders, which join with 3 customers on 3 distinct keys
+        let (schema, _) = run_query(
+            &catalog,
+            "SELECT * FROM customer, orders WHERE c_custkey = o_custkey AND o_totalprice > 50.0",
+        );
+        assert_eq!(schema.get_no_tuples(), 2);
+
+        let (schema, _) = run_query(
+            &catalog,
+            "SELECT DISTINCT c_name FROM customer WHERE c_custkey > 1",
+        );
+        assert_eq!(schema.get_no_tuples(), 1);
+    }
+
+    #[test]
+    fn test_sum_without_group_by() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT SUM(o_totalprice) FROM orders WHERE o_custkey < 3",
+        );
+
+        assert_eq!(schema.get_atts()[0].name, "SUM(o_totalprice)");
+        assert_eq!(rows, vec!["425.75|"]);
+
+        let (schema, rows) = run_query(&catalog, "SELECT SUM(o_orderkey * 2) FROM orders");
+
+        assert_eq!(schema.find_type("SUM(o_orderkey * 2)"), Some(Type::Integer));
+        assert_eq!(rows, vec!["92|"]);
+    }
+
+    #[test]
+    fn test_sum_overflow() {
+        let dir = TempDir::new().unwrap();
+        let catalog = create_test_catalog(
+            &dir,
+            &[Table {
+                name: "big",
+                atts: &[("a", "INTEGER"), ("b", "INTEGER")],
+                rows: &["9223372036854775807|1|", "1|1|", "5|2|"],
+            }],
+        );
+
+        for query in [
+            "SELECT SUM(a) FROM big",
+            "SELECT SUM(a), b FROM big GROUP BY b",
+        ] {
+            let compiler = QueryCompiler::new(&catalog);
+            let (_, mut relop) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+            relop.by_ref().for_each(drop);
+
+            let error = relop.take_error().expect(query);
+            assert!(error.to_string().contains("overflow"), "{error}");
+        }
+
+        // the other aggregates don't overflow on the same values
+        let (_, rows) = run_query(&catalog, "SELECT COUNT(a), MAX(a), AVG(b) FROM big");
+        assert_eq!(rows, vec!["3|9223372036854775807|1.3333333333333333|"]);
+    }
+
+    #[test]
+    fn test_sum_over_empty_input() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT SUM(o_totalprice) FROM orders WHERE o_totalprice > 1000.0",
+        );
+        assert_eq!(rows, vec!["|"]);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT SUM(o_totalprice), o_custkey FROM orders WHERE o_totalprice > 1000.0 GROUP BY o_custkey",
+        );
+        assert!(rows.is_empty());
+    }
+
+    #[test]
+    fn test_sum_group_by() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT SUM(o_totalprice), o_custkey FROM orders GROUP BY o_custkey",
+        );
+
+        assert_eq!(rows, vec!["10|4|", "350.5|1|", "75.25|2|"]);
+    }
+
+    #[test]
+    fn test_sum_group_by_over_join() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, SUM(o_totalprice) FROM customer, orders WHERE c_custkey = o_custkey GROUP BY c_name",
+        );
+
+        assert_eq!(rows, vec!["Alice|350.5|", "Bob|75.25|"]);
+
+        // the padded order of Carol is ignored by the sum
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, SUM(o_totalprice) FROM customer LEFT JOIN orders ON c_custkey = o_custkey GROUP BY c_name",
+        );
+
+        assert_eq!(rows, vec!["Alice|350.5|", "Bob|75.25|", "Carol||"]);
+    }
+
+    #[test]
+    fn test_count_avg_min_max() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT COUNT(*), COUNT(DISTINCT o_custkey), AVG(o_orderkey), MIN(o_totalprice), MAX(o_totalprice) FROM orders",
+        );
+
+        let names = schema
+            .get_atts()
+            .iter()
+            .map(|att| att.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "COUNT(*)",
+                "COUNT(DISTINCT o_custkey)",
+                "AVG(o_orderkey)",
+                "MIN(o_totalprice)",
+                "MAX(o_totalprice)"
+            ]
+        );
+        assert_eq!(rows, vec!["4|3|11.5|10|250.5|"]);
+    }
+
+    #[test]
+    fn test_count_ignores_padded_nulls() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (_, rows) = run_query(
+            &catalog,
+            "SELECT c_name, COUNT(o_orderkey), COUNT(*), AVG(o_totalprice) FROM customer LEFT JOIN orders ON c_custkey = o_custkey GROUP BY c_name",
+        );
+
+        assert_eq!(
+            rows,
+            vec!["Alice|2|2|175.25|", "Bob|1|1|75.25|", "Carol|0|1||"]
+        );
+    }
+
+    #[test]
+    fn test_min_max_on_strings() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT MIN(c_name), MAX(c_name), COUNT(DISTINCT c_name) FROM customer",
+        );
+
+        assert_eq!(schema.find_type("MIN(c_name)"), Some(Type::String));
+        assert_eq!(rows, vec!["Alice|Carol|3|"]);
+    }
+
+    #[test]
+    fn test_having() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT o_custkey, SUM(o_totalprice) FROM orders GROUP BY o_custkey HAVING SUM(o_totalprice) > 50.0",
+        );
+
+        assert_eq!(schema.get_num_atts(), 2);
+        assert_eq!(rows, vec!["1|350.5|", "2|75.25|"]);
+
+        // the aggregate in the HAVING clause doesn't have to be selected
+        let (schema, rows) = run_query(
+            &catalog,
+            "SELECT c_name FROM customer, orders WHERE c_custkey = o_custkey GROUP BY c_name HAVING COUNT(*) > 1 OR c_name = 'Bob'",
+        );
+
+        assert_eq!(schema.get_num_atts(), 1);
+        assert_eq!(rows, vec!["Alice|", "Bob|"]);
+
+        let (_, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_custkey, COUNT(*) FROM orders GROUP BY o_custkey HAVING COUNT(*) < 2 ORDER BY o_custkey DESC",
+        );
+
+        assert_eq!(rows, vec!["4|1|", "2|1|"]);
+    }
+
+    #[test]
+    fn test_having_errors() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        for query in [
+            "SELECT o_custkey FROM orders WHERE SUM(o_totalprice) > 50.0",
+            "SELECT o_custkey FROM orders GROUP BY o_custkey HAVING o_orderkey > 10",
+            "SELECT o_custkey FROM orders GROUP BY o_custkey HAVING SUM(o_totalprice) > 50",
+        ] {
+            assert!(
+                compiler.compile_ast(parse(query).unwrap()).is_err(),
+                "{query}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_by_errors() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        for query in [
+            "SELECT o_orderkey, SUM(o_totalprice) FROM orders GROUP BY o_custkey",
+            "SELECT SUM(o_totalprice) FROM orders GROUP BY missing",
+            "SELECT SUM(c_name) FROM customer",
+            "SELECT AVG(c_name) FROM customer",
+            "SELECT MAX(c_name + 1) FROM customer",
+        ] {
+            assert!(
+                compiler.compile_ast(parse(query).unwrap()).is_err(),
+                "{query}"
+            );
+        }
+    }
+
+    // `run_query` sorts its output, so this keeps the order the operators produced
+    fn run_query_ordered(catalog: &Catalog, query: &str) -> (String, Vec<String>) {
+        let compiler = QueryCompiler::new(catalog);
+        let (_, root) = compiler.compile_ast(parse(query).unwrap()).unwrap();
+        let mut tree = QueryExecutionTree { root };
+        let plan = tree.as_string();
+
+        let rows = tree
+            .by_ref()
+            .map(|record| {
+                String::from_utf8(record.to_bytes())
+                    .unwrap()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        if let Some(error) = tree.take_error() {
+            panic!("{query} failed: {error}");
+        }
+
+        (plan, rows)
+    }
+
+    #[test]
+    fn test_limit_offset() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+
+        let (plan, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_orderkey FROM orders ORDER BY o_totalprice DESC LIMIT 2",
+        );
+        assert!(plan.contains("OrderBy(top 2)"), "{plan}");
+        assert_eq!(rows, vec!["11|", "10|"]);
+
+        let (_, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_orderkey FROM orders ORDER BY o_totalprice LIMIT 2 OFFSET 1",
+        );
+        assert_eq!(rows, vec!["12|", "10|"]);
+
+        let (_, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_orderkey FROM orders ORDER BY o_orderkey LIMIT 10 OFFSET 3",
+        );
+        assert_eq!(rows, vec!["13|"]);
+
+        let (_, rows) = run_query_ordered(&catalog, "SELECT * FROM orders LIMIT 0");
+        assert!(rows.is_empty());
+
+        let (plan, rows) = run_query_ordered(&catalog, "SELECT o_orderkey FROM orders LIMIT 3");
+        assert!(!plan.contains("OrderBy"), "{plan}");
+        assert_eq!(rows.len(), 3);
+
+        // limits far beyond the input neither overflow nor get allocated up front
+        let (_, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_orderkey FROM orders ORDER BY o_orderkey LIMIT 18446744073709551615 OFFSET 1",
+        );
+        assert_eq!(rows, vec!["11|", "12|", "13|"]);
+
+        let (_, rows) = run_query_ordered(
+            &catalog,
+            "SELECT o_orderkey FROM orders ORDER BY o_orderkey DESC LIMIT 10000000000000",
+        );
+        assert_eq!(rows, vec!["13|", "12|", "11|", "10|"]);
+    }
+
+    #[test]
+    fn test_negative_limit() {
+        assert!(parse("SELECT * FROM orders LIMIT -1").is_err());
+    }
+
+    #[test]
+    fn test_inner_join_unknown_attribute() {
+        let dir = TempDir::new().unwrap();
+        let catalog = customers_and_orders(&dir);
+        let compiler = QueryCompiler::new(&catalog);
+
+        let query =
+            parse("SELECT * FROM customer INNER JOIN orders ON c_custkey = missing").unwrap();
+        assert!(compiler.compile_ast(query).is_err());
+    }
+
+    #[test]
+    fn test_analyze() {
+        let dir = TempDir::new().unwrap();
+        let mut catalog = customers_and_orders(&dir);
+        catalog.set_no_tuples("orders", 1000);
+
+        assert!(matches!(
+            execute_statement(&mut catalog, "ANALYZE orders").unwrap(),
+            StatementResult::Done
+        ));
+        assert_eq!(catalog.get_no_tuples("orders"), Some(4));
+        assert_eq!(catalog.get_no_distinct("orders", "o_orderkey"), Some(4));
+        assert_eq!(catalog.get_no_distinct("orders", "o_custkey"), Some(3));
+
+        let statistics = catalog.get_statistics("orders", "o_totalprice").unwrap();
+        assert_eq!((statistics.min, statistics.max), (10.0, 250.5));
+        assert_eq!(statistics.histogram.first(), Some(&10.0));
+        assert_eq!(statistics.histogram.last(), Some(&250.5));
+        assert!(statistics.histogram.is_sorted());
+
+        execute_statement(&mut catalog, "analyze customer").unwrap();
+        assert_eq!(catalog.get_no_distinct("customer", "c_name"), Some(3));
+        assert_eq!(catalog.get_statistics("customer", "c_name"), None);
+
+        assert!(execute_statement(&mut catalog, "ANALYZE missing").is_err());
+
+        let tree = execute_statement(&mut catalog, "SELECT * FROM orders").unwrap();
+        assert!(matches!(tree, StatementResult::Query(_)));
+    }
+
+    #[test]
+    fn test_explain() {
+        let dir = TempDir::new().unwrap();
+        let mut catalog = customers_and_orders(&dir);
+        let query =
+            "SELECT c_name, o_orderkey FROM customer INNER JOIN orders ON c_custkey = o_custkey";
+
+        let StatementResult::Explain(plan) =
+            execute_statement(&mut catalog, &format!("EXPLAIN {query}")).unwrap()
+        else {
+            panic!("EXPLAIN should give the plan");
+        };
+        let operators = plan.lines().filter(|line| !line.trim().is_empty());
+        assert!(operators.clone().count() > 1);
+        assert!(
+            operators
+                .clone()
+                .all(|line| line.contains("rows=") && line.contains("cost="))
+        );
+        assert!(!plan.contains("actual"));
+
+        let StatementResult::Explain(plan) =
+            execute_statement(&mut catalog, &format!("explain analyze {query}")).unwrap()
+        else {
+            panic!("EXPLAIN ANALYZE should give the plan");
+        };
+        assert!(plan.lines().next().unwrap().contains("actual rows=3 "));
+        assert!(plan.contains("memory="));
     }
 }
@@ -0,0 +1,412 @@
+use crate::*;
+
+use super::QueryCompiler;
+use super::cost;
+use super::logical::*;
+
+impl QueryCompiler<'_> {
+    // Picks the operators that compute `plan` and opens the data files they read
+    pub(super) fn lower(&self, plan: LogicalPlan) -> anyhow::Result<RelOp> {
+        let schema = plan.schema().clone();
+        // handling every record of the input once
+        let records =
+            |input: &LogicalPlan| input.schema().get_no_tuples() as f64 * cost::RECORD_CPU_COST;
+
+        // the cost is only what the operator costs by itself
+        let (relop, cost) = match plan {
+            LogicalPlan::Scan { table, schema } => {
+                let path = self.catalog.get_data_file(&table).ok_or_else(|| {
+                    anyhow::anyhow!("Data file for table '{}' not found in catalog", table)
+                })?;
+                let cost = cost::scan_cost(&schema);
+
+                if path.is_empty() {
+                    (RelOp::EmptyTableScan, cost)
+                } else {
+                    let mut file = DBFile::new();
+                    if let Err(e) = file.open(&path) {
+                        // TODO: Make it actually fail, for now we just print the error and
+                        // continue with an empty scan as we just want the query plan to be
+                        // generated
+                        println!("{e}");
+                    }
+                    file.set_schema(schema);
+                    (RelOp::Scan(Scan { file }), cost)
+                }
+            }
+            LogicalPlan::Empty { .. } => (RelOp::EmptyTableScan, 0.0),
+            LogicalPlan::Product { .. } => {
+                anyhow::bail!("FROM lists have to be ordered into joins before they can run")
+            }
+            LogicalPlan::Select {
+                predicate,
+                constants,
+                input,
+                ..
+            } => {
+                let cost = records(&input);
+                let relop = RelOp::Select(Select {
+                    producer: Box::new(self.lower(*input)?),
+                    predicate,
+                    constants,
+                });
+
+                (relop, cost)
+            }
+            LogicalPlan::Project { atts, input, .. } => {
+                let cost = records(&input);
+                let relop = RelOp::Project(Project {
+                    producer: Box::new(self.lower(*input)?),
+                    atts_to_keep: atts,
+                });
+
+                (relop, cost)
+            }
+            LogicalPlan::Join {
+                join_type,
+                predicate,
+                left,
+                right,
+                ..
+            } => {
+                let left_schema = left.schema().clone();
+                let right_schema = right.schema().clone();
+                let predicate = predicate.into_join_predicate(left_schema.get_num_atts());
+
+                let relop = self.choose_join(
+                    predicate,
+                    join_type,
+                    self.lower(*left)?,
+                    self.lower(*right)?,
+                    &left_schema,
+                    &right_schema,
+                );
+
+                let cost = match &relop {
+                    RelOp::NestedLoopJoin(_) => {
+                        cost::nested_loop_join_cost(&left_schema, &right_schema)
+                    }
+                    RelOp::HashJoin(join) if join.fill_left => {
+                        cost::hash_join_cost(&left_schema, &right_schema, self.memory_limit)
+                    }
+                    RelOp::HashJoin(_) => {
+                        cost::hash_join_cost(&right_schema, &left_schema, self.memory_limit)
+                    }
+                    // the sorts a merge join needs are operators of their own
+                    _ => cost::merge_join_cost(
+                        &left_schema,
+                        &right_schema,
+                        true,
+                        true,
+                        self.memory_limit,
+                    ),
+                };
+
+                (relop, cost)
+            }
+            LogicalPlan::GroupBy {
+                grouping,
+                aggregates,
+                input,
+                ..
+            } => {
+                let cost = records(&input);
+                let schema = input.schema().clone();
+                let grouping_atts = grouping
+                    .iter()
+                    .map(|att| schema.index_of(att).unwrap() as i32)
+                    .collect::<Vec<_>>();
+                let functions = aggregates
+                    .iter()
+                    .map(|aggregate| aggregate_function(aggregate, &schema))
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+
+                // grouping works on runs of records with the same grouping values
+                let producer = self.lower(*input)?;
+                let producer = if grouping_atts.is_empty() {
+                    producer
+                } else {
+                    self.sort(
+                        producer,
+                        OrderMaker::from_atts(&schema, &grouping_atts),
+                        &schema,
+                    )
+                };
+
+                let relop = RelOp::GroupBy(GroupBy {
+                    grouping: OrderMaker::from_atts(&schema, &grouping_atts),
+                    aggregates: functions,
+                    next_record: None,
+                    done: false,
+                    error: None,
+                    producer: Box::new(producer),
+                });
+
+                (relop, cost)
+            }
+            LogicalPlan::DupElim { input, .. } => {
+                let cost = records(&input);
+                let relop = RelOp::DupElim(DupElim {
+                    seen: std::collections::HashSet::new(),
+                    producer: Box::new(self.lower(*input)?),
+                });
+
+                (relop, cost)
+            }
+            LogicalPlan::OrderBy {
+                atts,
+                ascending,
+                limit,
+                input,
+                schema,
+            } => {
+                let cost = cost::sort_cost(&schema, self.memory_limit);
+                let relop = RelOp::OrderBy(OrderBy {
+                    ordering: OrderMaker::from_atts(&schema, &atts),
+                    records: Vec::new(),
+                    producer: Box::new(self.lower(*input)?),
+                    ascending,
+                    limit,
+                    schema,
+                    memory_limit: self.memory_limit,
+                    runs: std::collections::BinaryHeap::new(),
+                    error: None,
+                });
+
+                (relop, cost)
+            }
+            LogicalPlan::Limit {
+                limit,
+                offset,
+                input,
+                ..
+            } => {
+                // it stops reading its input after `limit + offset` records
+                let read = input
+                    .schema()
+                    .get_no_tuples()
+                    .min(limit.saturating_add(offset) as u64);
+                let cost = read as f64 * cost::RECORD_CPU_COST;
+                let relop = RelOp::Limit(Limit {
+                    limit,
+                    offset,
+                    seen: 0,
+                    producer: Box::new(self.lower(*input)?),
+                });
+
+                (relop, cost)
+            }
+        };
+
+        Ok(self.instrument(relop, &schema, cost))
+    }
+
+    // Wraps `relop` with the estimates for it when the plan gets explained. `cost` is what the
+    // operator costs by itself, the cost of its producers gets added to it here.
+    fn instrument(&self, relop: RelOp, schema: &Schema, cost: f64) -> RelOp {
+        if !self.explain {
+            return relop;
+        }
+
+        let below = relop
+            .producers()
+            .into_iter()
+            .map(|producer| match producer {
+                RelOp::Instrument(instrument) => instrument.estimate.cost,
+                _ => 0.0,
+            })
+            .sum::<f64>();
+
+        RelOp::Instrument(Instrument {
+            estimate: Estimate {
+                rows: schema.get_no_tuples(),
+                cost: cost + below,
+            },
+            stats: ExecutionStats::default(),
+            producer: Box::new(relop),
+        })
+    }
+
+    // Sorts the records of `producer` in ascending order on `ordering`
+    fn sort(&self, producer: RelOp, ordering: OrderMaker, schema: &Schema) -> RelOp {
+        let sort = RelOp::OrderBy(OrderBy {
+            ordering,
+            records: Vec::new(),
+            producer: Box::new(producer),
+            ascending: true,
+            limit: None,
+            schema: schema.clone(),
+            memory_limit: self.memory_limit,
+            runs: std::collections::BinaryHeap::new(),
+            error: None,
+        });
+
+        self.instrument(sort, schema, cost::sort_cost(schema, self.memory_limit))
+    }
+
+    // Estimated cost of every join operator that can evaluate `predicate`, in the order nested
+    // loop, hash and merge join. Hash and merge joins only work for equi-joins.
+    fn join_costs(
+        &self,
+        predicate: &Cnf,
+        left: &Schema,
+        right: &Schema,
+        left_sorted: bool,
+        right_sorted: bool,
+    ) -> (f64, Option<f64>, Option<f64>) {
+        let nested_loop = cost::nested_loop_join_cost(left, right);
+
+        if !predicate.is_equi_join() {
+            return (nested_loop, None, None);
+        }
+
+        let hash = if cost::size(left) <= cost::size(right) {
+            cost::hash_join_cost(left, right, self.memory_limit)
+        } else {
+            cost::hash_join_cost(right, left, self.memory_limit)
+        };
+        let merge =
+            cost::merge_join_cost(left, right, left_sorted, right_sorted, self.memory_limit);
+
+        (nested_loop, Some(hash), Some(merge))
+    }
+
+    // The cost of joining `left` and `right` into `joined` with the cheapest join operator, plus
+    // building the records of the result
+    pub(super) fn compute_join_cost(
+        &self,
+        predicate: &Cnf,
+        left: &Schema,
+        right: &Schema,
+        joined: &Schema,
+    ) -> f64 {
+        let (nested_loop, hash, merge) = self.join_costs(predicate, left, right, false, false);
+        let join = [Some(nested_loop), hash, merge]
+            .into_iter()
+            .flatten()
+            .fold(f64::INFINITY, f64::min);
+
+        join + joined.get_no_tuples() as f64 * cost::RECORD_CPU_COST
+    }
+
+    // Picks the cheapest join operator that can evaluate `predicate`, using the cardinalities from
+    // the catalog
+    pub(super) fn choose_join(
+        &self,
+        predicate: Cnf,
+        join_type: JoinType,
+        left: RelOp,
+        right: RelOp,
+        left_schema: &Schema,
+        right_schema: &Schema,
+    ) -> RelOp {
+        let left_atts = left_schema.get_num_atts();
+        let right_atts = right_schema.get_num_atts();
+
+        let (left_ordering, right_ordering) = predicate.get_sort_orders();
+        let left_sorted = is_sorted_on(&left, &left_ordering);
+        let right_sorted = is_sorted_on(&right, &right_ordering);
+
+        let (nested_loop_cost, hash_cost, merge_cost) = self.join_costs(
+            &predicate,
+            left_schema,
+            right_schema,
+            left_sorted,
+            right_sorted,
+        );
+        let hash_cost = hash_cost.unwrap_or(f64::INFINITY);
+        let merge_cost = merge_cost.unwrap_or(f64::INFINITY);
+
+        // the smaller side gets built into the hash table
+        let fill_left = cost::size(left_schema) <= cost::size(right_schema);
+
+        if nested_loop_cost < hash_cost && nested_loop_cost < merge_cost {
+            RelOp::NestedLoopJoin(NestedLoopJoin {
+                predicate,
+                join_type,
+
+                left_atts,
+                right_atts,
+
+                records: Vec::new(),
+
+                left_producer: Box::new(left),
+                right_producer: Box::new(right),
+            })
+        } else if merge_cost <= hash_cost {
+            let left = if left_sorted {
+                left
+            } else {
+                self.sort(left, left_ordering.clone(), left_schema)
+            };
+            let right = if right_sorted {
+                right
+            } else {
+                self.sort(right, right_ordering.clone(), right_schema)
+            };
+
+            RelOp::MergeJoin(MergeJoin {
+                buf: Vec::new(),
+                predicate,
+                join_type,
+                left_atts,
+                right_atts,
+                left_ordering,
+                right_ordering,
+                started: false,
+                left_record: None,
+                right_record: None,
+                left_producer: Box::new(left),
+                right_producer: Box::new(right),
+            })
+        } else {
+            let (left_projection, right_projection) = predicate.get_projections();
+
+            RelOp::HashJoin(HashJoin {
+                predicate,
+                join_type,
+                fill_left,
+                left_atts,
+                right_atts,
+                hash_table: std::collections::HashMap::new(),
+                filled: false,
+                buf: Vec::new(),
+                left_projection,
+                right_projection,
+                memory_limit: self.memory_limit,
+                left_schema: left_schema.clone(),
+                right_schema: right_schema.clone(),
+                partitions: Vec::new(),
+                probe_partition: None,
+                block_join: None,
+                error: None,
+                left_producer: Box::new(left),
+                right_producer: Box::new(right),
+            })
+        }
+    }
+}
+
+// Whether `relop` is known to produce its records in ascending order on `ordering`
+fn is_sorted_on(relop: &RelOp, ordering: &OrderMaker) -> bool {
+    let starts_with = |sorted: &OrderMaker| {
+        sorted.atts.len() >= ordering.atts.len()
+            && sorted
+                .atts
+                .iter()
+                .zip(&ordering.atts)
+                .all(|(sorted, wanted)| sorted.0 == wanted.0)
+    };
+
+    match relop {
+        RelOp::OrderBy(order_by) => order_by.ascending && starts_with(&order_by.ordering),
+        RelOp::Select(select) => is_sorted_on(&select.producer, ordering),
+        RelOp::Limit(limit) => is_sorted_on(&limit.producer, ordering),
+        RelOp::Instrument(instrument) => is_sorted_on(&instrument.producer, ordering),
+        // padding for unmatched right records breaks up the order of the left side
+        RelOp::MergeJoin(join) => {
+            !join.join_type.preserves_right() && starts_with(&join.left_ordering)
+        }
+        _ => false,
+    }
+}
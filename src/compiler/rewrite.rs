@@ -0,0 +1,549 @@
+use crate::*;
+use std::collections::HashSet;
+
+use super::QueryCompiler;
+use super::cost;
+use super::logical::*;
+use super::rules;
+
+impl QueryCompiler<'_> {
+    // Turns the plan as written into one that computes the same records for less: the rules
+    // simplify it, FROM lists get ordered into joins with the filters as low as they can go,
+    // attributes get dropped as soon as nothing above needs them, and sorts under a LIMIT only
+    // keep what makes it past the limit
+    pub(super) fn rewrite(&self, plan: LogicalPlan) -> anyhow::Result<LogicalPlan> {
+        let plan = rules::apply_rules(plan, &self.rules)?;
+        let plan = self.order_joins(plan)?;
+
+        let required = attribute_names(plan.schema());
+        let plan = prune(plan, &required, false)?;
+
+        limit_sorts(plan)
+    }
+
+    fn order_joins(&self, plan: LogicalPlan) -> anyhow::Result<LogicalPlan> {
+        match plan {
+            LogicalPlan::Select {
+                predicate,
+                constants,
+                input,
+                ..
+            } if matches!(*input, LogicalPlan::Product { .. }) => {
+                let LogicalPlan::Product { inputs, schema } = *input else {
+                    unreachable!()
+                };
+                self.join_product(inputs, predicate, constants, schema)
+            }
+            LogicalPlan::Product { inputs, schema } => {
+                self.join_product(inputs, Cnf::new(), Record::new(), schema)
+            }
+            plan => plan.map_inputs(|input| self.order_joins(input)),
+        }
+    }
+
+    // Joins the inputs of a FROM list on `predicate`, which is compiled against all of their
+    // attributes
+    fn join_product(
+        &self,
+        inputs: Vec<LogicalPlan>,
+        predicate: Cnf,
+        constants: Record,
+        schema: Schema,
+    ) -> anyhow::Result<LogicalPlan> {
+        // every comparison on attributes gets pushed down to the lowest input or join that has
+        // them, only the ones without any are left for the top
+        let (constant, predicate) = predicate.partition_constant();
+
+        // the parts of the cnf that only need a single input get evaluated right above it, so
+        // join ordering works with the records that get through instead of the full tables
+        let inputs = inputs
+            .into_iter()
+            .map(|input| {
+                let input = self.order_joins(input)?;
+                let pushed = predicate.project_to_schema(&schema, input.schema());
+
+                if pushed.is_empty() {
+                    Ok(input)
+                } else {
+                    Ok(LogicalPlan::select(input, pushed, constants.clone()))
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        // the joins can put the attributes in any order, but everything above refers to them by
+        // their position in the FROM list
+        let order = schema
+            .get_atts()
+            .iter()
+            .map(|att| att.name.clone())
+            .collect::<Vec<_>>();
+
+        let cnf = Some((predicate, constants.clone(), schema));
+        let plan = if inputs.len() <= 4 {
+            self.dynamic_scan_order(cnf, inputs)?
+        } else {
+            self.greedy_scan_order(cnf, inputs)?
+        };
+        let plan = project_names(plan, &order);
+
+        if constant.is_empty() {
+            Ok(plan)
+        } else {
+            Ok(LogicalPlan::select(plan, constant, constants))
+        }
+    }
+
+    // Selinger style dynamic programming: finds the cheapest plan for every subset of the inputs,
+    // from the plans for the pairs of smaller subsets that make it up. Unless `bushy_plans` is set
+    // the right side of a join is always a single input, which keeps the plans left-deep.
+    pub(super) fn dynamic_scan_order(
+        &self,
+        cnf: Option<(Cnf, Record, Schema)>,
+        scans: Vec<LogicalPlan>,
+    ) -> anyhow::Result<LogicalPlan> {
+        struct Plan {
+            // cross products are only used if there's no other way to join the subset
+            cross_products: usize,
+            cost: f64,
+            schema: Schema,
+            // the subsets joined to get this one, `None` for a single input
+            split: Option<(usize, usize)>,
+        }
+
+        if scans.is_empty() {
+            anyhow::bail!("No scans to join");
+        }
+
+        // subsets of the inputs are bitmasks, so every proper subset of a set is a smaller number
+        // and has its plan worked out already
+        let full = (1usize << scans.len()) - 1;
+        let mut best: Vec<Option<Plan>> = (0..=full).map(|_| None).collect();
+
+        for (i, scan) in scans.iter().enumerate() {
+            best[1 << i] = Some(Plan {
+                cross_products: 0,
+                cost: cost::scan_cost(scan.schema()),
+                schema: scan.schema().clone(),
+                split: None,
+            });
+        }
+
+        for set in 1..=full {
+            if set.count_ones() < 2 {
+                continue;
+            }
+
+            let mut best_plan: Option<Plan> = None;
+
+            // every way of splitting the set in a non-empty left and right part
+            let mut left_set = (set - 1) & set;
+            while left_set != 0 {
+                let right_set = set ^ left_set;
+                let split = (left_set, right_set);
+                left_set = (left_set - 1) & set;
+
+                if !self.bushy_plans && right_set.count_ones() != 1 {
+                    continue;
+                }
+
+                let (Some(left), Some(right)) = (&best[split.0], &best[split.1]) else {
+                    continue;
+                };
+
+                let (predicate, _, schema) = plan_join(&cnf, &left.schema, &right.schema);
+                let predicate = predicate.into_join_predicate(left.schema.get_num_atts());
+
+                let cross_products = left.cross_products
+                    + right.cross_products
+                    + predicate.equi_join_atts().is_empty() as usize;
+                let cost = left.cost
+                    + right.cost
+                    + self.compute_join_cost(&predicate, &left.schema, &right.schema, &schema);
+
+                let is_better = match &best_plan {
+                    Some(plan) => (cross_products, cost) < (plan.cross_products, plan.cost),
+                    None => true,
+                };
+
+                if is_better {
+                    best_plan = Some(Plan {
+                        cross_products,
+                        cost,
+                        schema,
+                        split: Some(split),
+                    });
+                }
+            }
+
+            best[set] = best_plan;
+        }
+
+        // follows the splits of the best plan for the whole set down to the inputs
+        fn build(
+            cnf: &Option<(Cnf, Record, Schema)>,
+            best: &mut [Option<Plan>],
+            scans: &mut [Option<LogicalPlan>],
+            set: usize,
+        ) -> LogicalPlan {
+            match best[set].take().unwrap().split {
+                None => scans[set.trailing_zeros() as usize].take().unwrap(),
+                Some((left_set, right_set)) => {
+                    let left = build(cnf, best, scans, left_set);
+                    let right = build(cnf, best, scans, right_set);
+
+                    join_inputs(cnf, left, right)
+                }
+            }
+        }
+
+        let mut scans: Vec<_> = scans.into_iter().map(Some).collect();
+
+        Ok(build(&cnf, &mut best, &mut scans, full))
+    }
+
+    // Starts with the pair of inputs with the smallest join, then keeps adding the input that
+    // keeps the intermediate result smallest. Inputs that can be joined on an equality always go
+    // before cross products.
+    fn greedy_scan_order(
+        &self,
+        cnf: Option<(Cnf, Record, Schema)>,
+        mut scans: Vec<LogicalPlan>,
+    ) -> anyhow::Result<LogicalPlan> {
+        if scans.len() == 1 {
+            return Ok(scans.pop().unwrap());
+        }
+
+        // how big the join of `left` and `right` gets, cross products sort after everything else
+        let estimate = |left: &Schema, right: &Schema| {
+            let (predicate, _, joined) = plan_join(&cnf, left, right);
+            let predicate = predicate.into_join_predicate(left.get_num_atts());
+
+            (
+                predicate.equi_join_atts().is_empty(),
+                joined.get_no_tuples(),
+            )
+        };
+
+        let (first, second) = (0..scans.len())
+            .flat_map(|i| {
+                (0..scans.len())
+                    .filter(move |&j| j != i)
+                    .map(move |j| (i, j))
+            })
+            .min_by_key(|&(i, j)| estimate(scans[i].schema(), scans[j].schema()))
+            .ok_or_else(|| anyhow::anyhow!("Need at least two scans to order joins"))?;
+
+        let mut scans: Vec<_> = scans.into_iter().map(Some).collect();
+        let mut plan = scans[first].take().unwrap();
+        let mut next = second;
+
+        loop {
+            let next_scan = scans[next].take().unwrap();
+            plan = join_inputs(&cnf, plan, next_scan);
+
+            let candidate = scans
+                .iter()
+                .enumerate()
+                .filter_map(|(i, scan)| scan.as_ref().map(|scan| (i, scan.schema())))
+                .min_by_key(|(_, next_schema)| estimate(plan.schema(), next_schema));
+
+            match candidate {
+                Some((i, _)) => next = i,
+                None => break,
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+// The disjunctions of the WHERE cnf that need attributes of both `left` and `right`, so the join
+// of the two is the lowest place they can go. Anything that only needs one of the sides has been
+// evaluated further down already. Returns the comparisons between attributes, numbered like the
+// attributes of the joined records, the comparisons against literals, which need a `Select` over
+// the joined records, and the estimated schema of the result.
+fn plan_join(
+    cnf: &Option<(Cnf, Record, Schema)>,
+    left: &Schema,
+    right: &Schema,
+) -> (Cnf, Cnf, Schema) {
+    let Some((cnf, constants, cnf_schema)) = cnf else {
+        let schema = join_schema(JoinType::Inner, &Cnf::new(), left, right);
+        return (Cnf::new(), Cnf::new(), schema);
+    };
+
+    let mut schema = left.clone();
+    schema.append(right);
+
+    let left_atts = left.get_num_atts() as i32;
+    let mut covered = cnf.project_to_schema(cnf_schema, &schema);
+    covered.and_list.retain(|disjunction| {
+        let (mut on_left, mut on_right) = (false, false);
+        for comparison in &disjunction.or_list {
+            for (operand, which_att) in [
+                (comparison.operand1, comparison.which_att1),
+                (comparison.operand2, comparison.which_att2),
+            ] {
+                if operand != Target::Literal {
+                    on_left |= which_att < left_atts;
+                    on_right |= which_att >= left_atts;
+                }
+            }
+        }
+
+        on_left && on_right
+    });
+
+    let (filter, predicate) = covered.partition_literals();
+
+    let mut schema = join_schema(JoinType::Inner, &predicate, left, right);
+    cost::estimate_select(&mut schema, &filter, constants);
+
+    (predicate, filter, schema)
+}
+
+// Joins `left` and `right` on whatever part of the cnf needs both of them
+fn join_inputs(
+    cnf: &Option<(Cnf, Record, Schema)>,
+    left: LogicalPlan,
+    right: LogicalPlan,
+) -> LogicalPlan {
+    let (predicate, filter, _) = plan_join(cnf, left.schema(), right.schema());
+    let plan = LogicalPlan::join(JoinType::Inner, predicate, left, right);
+
+    match cnf {
+        Some((_, constants, _)) if !filter.is_empty() => {
+            LogicalPlan::select(plan, filter, constants.clone())
+        }
+        _ => plan,
+    }
+}
+
+fn attribute_names(schema: &Schema) -> HashSet<String> {
+    schema
+        .get_atts()
+        .iter()
+        .map(|att| att.name.clone())
+        .collect()
+}
+
+// The names of the attributes of `schema` that `cnf` compares
+fn cnf_attributes(cnf: &Cnf, schema: &Schema) -> Vec<String> {
+    cnf.and_list
+        .iter()
+        .flat_map(|disjunction| &disjunction.or_list)
+        .flat_map(|comparison| {
+            [
+                (comparison.operand1, comparison.which_att1),
+                (comparison.operand2, comparison.which_att2),
+            ]
+        })
+        .filter(|(operand, _)| *operand != Target::Literal)
+        .map(|(_, which_att)| schema.get_atts()[which_att as usize].name.clone())
+        .collect()
+}
+
+// Projects `plan` on the attributes called `names`, in that order, unless that's what it gives
+// already
+fn project_names(plan: LogicalPlan, names: &[String]) -> LogicalPlan {
+    let schema = plan.schema();
+    let atts = names
+        .iter()
+        .map(|name| schema.index_of(name).unwrap() as i32)
+        .collect::<Vec<_>>();
+
+    if atts == (0..schema.get_num_atts() as i32).collect::<Vec<_>>() {
+        plan
+    } else {
+        LogicalPlan::project(plan, atts)
+    }
+}
+
+// Takes the projections below `plan`, looking past selections, into `atts`, which are the
+// attributes of `plan` to keep. Gives the attributes to keep of what's left below, so a projection
+// right below another one doesn't hide which attributes are still needed.
+fn merge_projects(atts: Vec<i32>, plan: LogicalPlan) -> (Vec<i32>, LogicalPlan) {
+    match plan {
+        LogicalPlan::Project {
+            atts: inner, input, ..
+        } => {
+            let atts = atts.iter().map(|&att| inner[att as usize]).collect();
+            merge_projects(atts, *input)
+        }
+        LogicalPlan::Select {
+            predicate,
+            constants,
+            input,
+            ..
+        } => {
+            let input_schema = input.schema().clone();
+            let all = (0..input_schema.get_num_atts() as i32).collect();
+            let (inner, input) = merge_projects(all, *input);
+
+            let predicate = predicate.project_to_schema(&input_schema, input.schema());
+            let atts = atts.iter().map(|&att| inner[att as usize]).collect();
+
+            (atts, LogicalPlan::select(input, predicate, constants))
+        }
+        plan => (atts, plan),
+    }
+}
+
+// Drops the attributes nothing above needs. Only the attributes in `required` have to make it out
+// of `plan`. Projections go right above scans, joins and group bys, and only where `project` is
+// set, so a `Select` stays right above the scan it filters.
+fn prune(
+    plan: LogicalPlan,
+    required: &HashSet<String>,
+    project: bool,
+) -> anyhow::Result<LogicalPlan> {
+    let names = |schema: &Schema, atts: &[i32]| {
+        atts.iter()
+            .map(|&att| schema.get_atts()[att as usize].name.clone())
+            .collect::<Vec<_>>()
+    };
+    let positions = |schema: &Schema, names: &[String]| {
+        names
+            .iter()
+            .map(|name| schema.index_of(name).unwrap() as i32)
+            .collect::<Vec<_>>()
+    };
+
+    let plan = match plan {
+        LogicalPlan::Scan { .. } | LogicalPlan::Empty { .. } | LogicalPlan::Product { .. } => plan,
+        LogicalPlan::Select {
+            predicate,
+            constants,
+            input,
+            ..
+        } => {
+            let mut below = required.clone();
+            below.extend(cnf_attributes(&predicate, input.schema()));
+
+            let old_schema = input.schema().clone();
+            let input = prune(*input, &below, false)?;
+            let predicate = predicate.project_to_schema(&old_schema, input.schema());
+
+            LogicalPlan::select(input, predicate, constants)
+        }
+        LogicalPlan::Project { atts, input, .. } => {
+            let (atts, input) = merge_projects(atts, *input);
+            let mut kept = names(input.schema(), &atts);
+
+            // this projection can drop whatever isn't required as well, instead of another one
+            // above it
+            if project {
+                kept.retain(|name| required.contains(name));
+            }
+
+            let input = prune(input, &kept.iter().cloned().collect(), false)?;
+            project_names(input, &kept)
+        }
+        LogicalPlan::Join {
+            join_type,
+            predicate,
+            left,
+            right,
+            ..
+        } => {
+            let mut old_schema = left.schema().clone();
+            old_schema.append(right.schema());
+
+            let mut below = required.clone();
+            below.extend(cnf_attributes(&predicate, &old_schema));
+
+            let left = prune(*left, &below, true)?;
+            let right = prune(*right, &below, true)?;
+
+            let mut schema = left.schema().clone();
+            schema.append(right.schema());
+            let predicate = predicate.project_to_schema(&old_schema, &schema);
+
+            LogicalPlan::join(join_type, predicate, left, right)
+        }
+        LogicalPlan::GroupBy {
+            grouping,
+            aggregates,
+            input,
+            ..
+        } => {
+            let mut below: HashSet<String> = grouping.iter().cloned().collect();
+            for aggregate in &aggregates {
+                let atts = aggregate
+                    .expr()
+                    .map(|expr| expr.attributes())
+                    .unwrap_or_default();
+                below.extend(atts.into_iter().map(str::to_string));
+            }
+
+            let input = prune(*input, &below, true)?;
+            LogicalPlan::group_by(input, grouping, aggregates)?
+        }
+        // every attribute counts for telling records apart
+        LogicalPlan::DupElim { input, .. } => {
+            let below = attribute_names(input.schema());
+            LogicalPlan::dup_elim(prune(*input, &below, false)?)
+        }
+        LogicalPlan::OrderBy {
+            atts,
+            ascending,
+            limit,
+            input,
+            ..
+        } => {
+            let sorted = names(input.schema(), &atts);
+            let mut below = required.clone();
+            below.extend(sorted.iter().cloned());
+
+            let input = prune(*input, &below, false)?;
+            let atts = positions(input.schema(), &sorted);
+
+            LogicalPlan::order_by(input, atts, ascending, limit)
+        }
+        LogicalPlan::Limit {
+            limit,
+            offset,
+            input,
+            ..
+        } => LogicalPlan::limit(prune(*input, required, false)?, limit, offset),
+    };
+
+    let schema = plan.schema();
+    let keep = (0..schema.get_num_atts())
+        .filter(|&i| required.contains(&schema.get_atts()[i].name))
+        .map(|i| i as i32)
+        .collect::<Vec<_>>();
+
+    if !project || keep.len() == schema.get_num_atts() {
+        Ok(plan)
+    } else {
+        Ok(LogicalPlan::project(plan, keep))
+    }
+}
+
+// A sort right below a LIMIT only has to keep the records that make it past the limit
+fn limit_sorts(plan: LogicalPlan) -> anyhow::Result<LogicalPlan> {
+    let plan = plan.map_inputs(limit_sorts)?;
+
+    let LogicalPlan::Limit {
+        limit,
+        offset,
+        input,
+        ..
+    } = plan
+    else {
+        return Ok(plan);
+    };
+
+    let input = match *input {
+        LogicalPlan::OrderBy {
+            atts,
+            ascending,
+            input,
+            ..
+        } => LogicalPlan::order_by(*input, atts, ascending, Some(limit.saturating_add(offset))),
+        input => input,
+    };
+
+    Ok(LogicalPlan::limit(input, limit, offset))
+}
@@ -0,0 +1,462 @@
+use crate::*;
+use std::collections::HashSet;
+
+use super::logical::*;
+
+// How many times the rules get run over the whole plan at most, in case some of them keep undoing
+// each other and the plan never settles
+const MAX_PASSES: usize = 16;
+
+// What a rule did to the node it was given
+pub enum Applied {
+    Changed(LogicalPlan),
+    // the rule doesn't apply, the node is given back as it was
+    Unchanged(LogicalPlan),
+}
+
+// A rewrite of a single node of a logical plan into an equivalent one. The result has to give the
+// same attributes in the same order, since the node above refers to them by position.
+pub trait Rule {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied>;
+}
+
+pub fn default_rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(FoldConstants),
+        Box::new(RemoveConstantSelects),
+        Box::new(MergeSelects),
+        Box::new(MergeProjects),
+        Box::new(EliminateDupElim),
+    ]
+}
+
+// Applies the rules everywhere in the plan until none of them changes anything anymore
+pub fn apply_rules(mut plan: LogicalPlan, rules: &[Box<dyn Rule>]) -> anyhow::Result<LogicalPlan> {
+    for _ in 0..MAX_PASSES {
+        let changed;
+        (plan, changed) = apply_pass(plan, rules)?;
+
+        if !changed {
+            break;
+        }
+    }
+
+    Ok(plan)
+}
+
+// Runs every rule once on every node, the inputs of a node before the node itself
+fn apply_pass(plan: LogicalPlan, rules: &[Box<dyn Rule>]) -> anyhow::Result<(LogicalPlan, bool)> {
+    let mut changed = false;
+
+    let mut plan = plan.map_inputs(|input| {
+        let (input, input_changed) = apply_pass(input, rules)?;
+        changed |= input_changed;
+        Ok(input)
+    })?;
+
+    for rule in rules {
+        plan = match rule.apply(plan)? {
+            Applied::Changed(plan) => {
+                changed = true;
+                plan
+            }
+            Applied::Unchanged(plan) => plan,
+        };
+    }
+
+    Ok((plan, changed))
+}
+
+fn is_constant(comparison: &Comparison) -> bool {
+    comparison.operand1 == Target::Literal && comparison.operand2 == Target::Literal
+}
+
+// Works out comparisons between two literals while planning. A disjunction with one that's always
+// true is always true itself, and the ones that are always false can be left out of theirs.
+pub struct FoldConstants;
+
+impl Rule for FoldConstants {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied> {
+        let (predicate, constants, input) = match plan {
+            LogicalPlan::Select {
+                predicate,
+                constants,
+                input,
+                ..
+            } if predicate.comparisons().any(is_constant) => (predicate, constants, input),
+            plan => return Ok(Applied::Unchanged(plan)),
+        };
+
+        let mut folded = Cnf::new();
+        folded.is_false = predicate.is_false;
+
+        for disjunction in predicate.and_list {
+            // literals are read from the right record
+            let always_true = disjunction.or_list.iter().any(|comparison| {
+                is_constant(comparison) && comparison.run(&Record::new(), &constants)
+            });
+            if always_true {
+                continue;
+            }
+
+            let or_list = disjunction
+                .or_list
+                .into_iter()
+                .filter(|comparison| !is_constant(comparison))
+                .collect::<Vec<_>>();
+            if or_list.is_empty() {
+                folded = Cnf::new().negation();
+                break;
+            }
+
+            folded.and_list.push(Disjunction { or_list });
+        }
+
+        Ok(Applied::Changed(LogicalPlan::select(
+            *input, folded, constants,
+        )))
+    }
+}
+
+// Selections that let everything through (like WHERE TRUE) go away, and ones that never let
+// anything through (like WHERE FALSE) leave nothing of their input
+pub struct RemoveConstantSelects;
+
+impl Rule for RemoveConstantSelects {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied> {
+        Ok(match plan {
+            LogicalPlan::Select {
+                predicate, input, ..
+            } if predicate.is_empty() => Applied::Changed(*input),
+            LogicalPlan::Select {
+                predicate, schema, ..
+            } if predicate.is_false => Applied::Changed(LogicalPlan::empty(schema)),
+            plan => Applied::Unchanged(plan),
+        })
+    }
+}
+
+// A selection right above another one becomes a single selection on both predicates
+pub struct MergeSelects;
+
+impl Rule for MergeSelects {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied> {
+        let (mut predicate, constants, input) = match plan {
+            LogicalPlan::Select {
+                predicate,
+                constants,
+                input,
+                ..
+            } if matches!(*input, LogicalPlan::Select { .. }) => (predicate, constants, input),
+            plan => return Ok(Applied::Unchanged(plan)),
+        };
+
+        let LogicalPlan::Select {
+            predicate: inner,
+            constants: mut merged,
+            input,
+            ..
+        } = *input
+        else {
+            unreachable!()
+        };
+
+        // the literals of the outer predicate go after the ones of the inner one
+        predicate.increase_constants_offset(merged.len());
+        merged.merge_right(&constants);
+
+        Ok(Applied::Changed(LogicalPlan::select(
+            *input,
+            inner * predicate,
+            merged,
+        )))
+    }
+}
+
+// A projection right above another one becomes a single projection, and projections that keep
+// every attribute in the same order go away
+pub struct MergeProjects;
+
+impl Rule for MergeProjects {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied> {
+        Ok(match plan {
+            LogicalPlan::Project { atts, input, .. }
+                if atts == (0..input.schema().get_num_atts() as i32).collect::<Vec<_>>() =>
+            {
+                Applied::Changed(*input)
+            }
+            LogicalPlan::Project { atts, input, .. }
+                if matches!(*input, LogicalPlan::Project { .. }) =>
+            {
+                let LogicalPlan::Project {
+                    atts: inner, input, ..
+                } = *input
+                else {
+                    unreachable!()
+                };
+
+                let atts = atts.iter().map(|&att| inner[att as usize]).collect();
+                Applied::Changed(LogicalPlan::project(*input, atts))
+            }
+            plan => Applied::Unchanged(plan),
+        })
+    }
+}
+
+// Removing duplicates from records that can't have any doesn't do anything
+pub struct EliminateDupElim;
+
+impl Rule for EliminateDupElim {
+    fn apply(&self, plan: LogicalPlan) -> anyhow::Result<Applied> {
+        Ok(match plan {
+            LogicalPlan::DupElim { input, .. }
+                if is_unique_on(&input, &attribute_names(input.schema())) =>
+            {
+                Applied::Changed(*input)
+            }
+            plan => Applied::Unchanged(plan),
+        })
+    }
+}
+
+fn attribute_names(schema: &Schema) -> HashSet<&str> {
+    schema
+        .get_atts()
+        .iter()
+        .map(|att| att.name.as_str())
+        .collect()
+}
+
+// Whether no two records of `plan` have the same values for all of `atts`, as far as can be told
+// from the plan. Only what's known for sure counts, not estimates.
+fn is_unique_on(plan: &LogicalPlan, atts: &HashSet<&str>) -> bool {
+    match plan {
+        LogicalPlan::Empty { .. } => true,
+        LogicalPlan::DupElim { schema, .. } => attribute_names(schema).is_subset(atts),
+        // every group is a single record
+        LogicalPlan::GroupBy { grouping, .. } => {
+            grouping.iter().all(|att| atts.contains(att.as_str()))
+        }
+        // fewer records, or the same ones in another order, are just as unique
+        LogicalPlan::Select { input, .. }
+        | LogicalPlan::OrderBy { input, .. }
+        | LogicalPlan::Limit { input, .. } => is_unique_on(input, atts),
+        // projections keep the names of the attributes, so they work the same below
+        LogicalPlan::Project { input, .. } => is_unique_on(input, atts),
+        // a pair of unique records of either side only shows up once
+        LogicalPlan::Join {
+            join_type: JoinType::Inner,
+            left,
+            right,
+            ..
+        } => {
+            fn on<'a>(side: &'a LogicalPlan, atts: &HashSet<&str>) -> HashSet<&'a str> {
+                attribute_names(side.schema())
+                    .into_iter()
+                    .filter(|att| atts.contains(att))
+                    .collect()
+            }
+
+            is_unique_on(left, &on(left, atts)) && is_unique_on(right, &on(right, atts))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compiler::ast;
+
+    fn table(names: &[&str]) -> LogicalPlan {
+        let names = names
+            .iter()
+            .map(|name| name.to_string())
+            .collect::<Vec<_>>();
+        let types = vec!["INTEGER".to_string(); names.len()];
+        let distincts = vec![10; names.len()];
+
+        LogicalPlan::scan(
+            "t",
+            Schema::new(&names, &types, &distincts, 100, String::new()),
+        )
+    }
+
+    // `att op literal`, with the literal at `literal` in the constants
+    fn compare(att: i32, op: CompOp, literal: i32) -> Comparison {
+        Comparison {
+            operand1: Target::Left,
+            which_att1: att,
+            operand2: Target::Literal,
+            which_att2: literal,
+            att_type: Type::Integer,
+            op,
+        }
+    }
+
+    fn constants(values: &[i64]) -> Record {
+        let mut record = Record::new();
+        for &value in values {
+            record.push_int(value);
+        }
+        record
+    }
+
+    fn rewrite(plan: LogicalPlan) -> LogicalPlan {
+        apply_rules(plan, &default_rules()).unwrap()
+    }
+
+    fn names(plan: &LogicalPlan) -> Vec<&str> {
+        plan.schema()
+            .get_atts()
+            .iter()
+            .map(|att| att.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_fold_constants() {
+        // 1 = 1 is always true, so the first disjunction goes away, and 1 > 2 never is, so only
+        // a < 5 is left of the second one
+        let always_true = Comparison {
+            operand1: Target::Literal,
+            which_att1: 0,
+            operand2: Target::Literal,
+            which_att2: 0,
+            att_type: Type::Integer,
+            op: CompOp::Equal,
+        };
+        let never_true = Comparison {
+            op: CompOp::Greater,
+            which_att2: 1,
+            ..always_true.clone()
+        };
+        let predicate = Cnf::and(
+            always_true + compare(0, CompOp::Greater, 2),
+            never_true.clone() + compare(0, CompOp::Less, 2),
+        );
+
+        let plan = LogicalPlan::select(table(&["a"]), predicate, constants(&[1, 2, 5]));
+        let Applied::Changed(LogicalPlan::Select { predicate, .. }) =
+            FoldConstants.apply(plan).unwrap()
+        else {
+            panic!("expected the selection to be folded");
+        };
+        assert_eq!(predicate, compare(0, CompOp::Less, 2).into());
+
+        // without anything else in the disjunction the whole predicate is false
+        let plan = LogicalPlan::select(table(&["a"]), never_true.into(), constants(&[1, 2]));
+        let Applied::Changed(LogicalPlan::Select { predicate, .. }) =
+            FoldConstants.apply(plan).unwrap()
+        else {
+            panic!("expected the selection to be folded");
+        };
+        assert!(predicate.is_false);
+
+        let plan = LogicalPlan::select(
+            table(&["a"]),
+            compare(0, CompOp::Less, 0).into(),
+            constants(&[1]),
+        );
+        assert!(matches!(
+            FoldConstants.apply(plan).unwrap(),
+            Applied::Unchanged(_)
+        ));
+    }
+
+    #[test]
+    fn test_remove_constant_selects() {
+        let plan = rewrite(LogicalPlan::select(
+            table(&["a"]),
+            Cnf::new(),
+            Record::new(),
+        ));
+        assert!(matches!(plan, LogicalPlan::Scan { .. }));
+
+        let plan = LogicalPlan::select(table(&["a", "b"]), Cnf::new().negation(), Record::new());
+        let plan = rewrite(LogicalPlan::project(plan, vec![1]));
+        let LogicalPlan::Project { input, .. } = &plan else {
+            panic!("expected the projection to stay");
+        };
+        assert!(matches!(**input, LogicalPlan::Empty { .. }));
+        assert_eq!(names(input), vec!["a", "b"]);
+        assert_eq!(plan.schema().get_no_tuples(), 0);
+    }
+
+    #[test]
+    fn test_merge_selects() {
+        let inner = LogicalPlan::select(
+            table(&["a", "b"]),
+            compare(0, CompOp::Less, 0).into(),
+            constants(&[5]),
+        );
+        let plan = LogicalPlan::select(inner, compare(1, CompOp::Equal, 0).into(), constants(&[7]));
+
+        let LogicalPlan::Select {
+            predicate,
+            constants: merged,
+            input,
+            ..
+        } = rewrite(plan)
+        else {
+            panic!("expected a single selection");
+        };
+        assert!(matches!(*input, LogicalPlan::Scan { .. }));
+        assert_eq!(predicate.and_list.len(), 2);
+        assert_eq!(merged.len(), 2);
+
+        // the literal of the outer predicate moved behind the one of the inner predicate
+        let mut record = Record::new();
+        record.push_int(3);
+        record.push_int(7);
+        assert!(predicate.run(&record, &merged));
+        record = Record::new();
+        record.push_int(3);
+        record.push_int(5);
+        assert!(!predicate.run(&record, &merged));
+    }
+
+    #[test]
+    fn test_merge_projects() {
+        let plan = LogicalPlan::project(table(&["a", "b", "c"]), vec![2, 0]);
+        let plan = LogicalPlan::project(plan, vec![1]);
+        let LogicalPlan::Project { atts, input, .. } = rewrite(plan) else {
+            panic!("expected a single projection");
+        };
+        assert_eq!(atts, vec![0]);
+        assert!(matches!(*input, LogicalPlan::Scan { .. }));
+
+        let plan = rewrite(LogicalPlan::project(table(&["a", "b"]), vec![0, 1]));
+        assert!(matches!(plan, LogicalPlan::Scan { .. }));
+    }
+
+    #[test]
+    fn test_eliminate_dup_elim() {
+        let aggregate = || vec![ast::Aggregate::CountStar];
+
+        // the groups already have a single record each, even with only some of their attributes
+        let plan =
+            LogicalPlan::group_by(table(&["a", "b"]), vec!["a".to_string()], aggregate()).unwrap();
+        let plan = LogicalPlan::dup_elim(LogicalPlan::project(plan, vec![0]));
+        assert!(matches!(rewrite(plan), LogicalPlan::Project { .. }));
+
+        let plan = LogicalPlan::dup_elim(LogicalPlan::dup_elim(table(&["a"])));
+        let LogicalPlan::DupElim { input, .. } = rewrite(plan) else {
+            panic!("expected a single duplicate elimination");
+        };
+        assert!(matches!(*input, LogicalPlan::Scan { .. }));
+
+        // dropping a grouping attribute can make groups look the same
+        let plan = LogicalPlan::group_by(
+            table(&["a", "b", "c"]),
+            vec!["a".to_string(), "b".to_string()],
+            aggregate(),
+        )
+        .unwrap();
+        let plan = LogicalPlan::dup_elim(LogicalPlan::project(plan, vec![0, 2]));
+        assert!(matches!(rewrite(plan), LogicalPlan::DupElim { .. }));
+
+        let plan = LogicalPlan::dup_elim(table(&["a"]));
+        assert!(matches!(rewrite(plan), LogicalPlan::DupElim { .. }));
+    }
+}
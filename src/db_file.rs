@@ -1,6 +1,8 @@
+use crate::buffer_pool::*;
 use crate::record::*;
 use crate::schema::*;
 use crate::types::*;
+use std::collections::BTreeSet;
 use std::fs::{File, OpenOptions};
 use std::io::{BufReader, Read, Seek, SeekFrom, Write};
 use std::path::Path;
@@ -11,112 +13,533 @@ use anyhow::{Result, anyhow};
 const PAGE_SIZE: usize = 131072;
 
 /// Maximum number of records that can fit in a page (rough estimate)
-const MAX_RECORDS_PER_PAGE: usize = 1000;
+pub const MAX_RECORDS_PER_PAGE: usize = 1000;
+
+/// Size of the header at the start of every page: the number of slots and where the record data
+/// starts
+const PAGE_HEADER_SIZE: usize = 8;
+
+/// Every slot holds the offset and the length of its record
+pub const SLOT_SIZE: usize = 8;
+
+/// Size of the biggest record a page can take, in the format of `Record::to_binary`
+pub const MAX_RECORD_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - SLOT_SIZE;
+
+/// Set in the length of a slot that forwards to another page, the offset is the page then and
+/// the rest of the length the slot on it
+const FORWARD: usize = 1 << 31;
+
+/// Set in the length of a slot holding a record that moved here from another page
+const MOVED: usize = 1 << 30;
+
+/// What a slot of a page holds
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Slot {
+    // the record got deleted, the slot can be reused
+    Free,
+    Record { offset: usize, len: usize },
+    // the record grew too big for this page and lives at the given place now
+    Forward(RecordId),
+    // a record that moved here, it's still known by the slot forwarding to it
+    Moved { offset: usize, len: usize },
+}
 
-/// A database page that holds multiple records
+/// A slotted page that holds multiple records. The slot directory grows from the front of the
+/// page, right after the header, while the records are packed from the back, so a record can be
+/// found by its slot without going through the ones before it.
 #[derive(Debug, Clone)]
 pub struct Page {
-    records: Vec<Record>,
-    num_records: usize,
-    current_size_bytes: usize,
+    // the page exactly as it's stored on disk
+    data: Vec<u8>,
+    // slot of the next record `get_first` hands out
+    first: usize,
+    // bytes of the records stored on the page and the number of free slots, so finding room
+    // doesn't have to go through the whole directory
+    used: usize,
+    free_slots: usize,
+}
+
+fn read_u32(bits: &[u8], at: usize) -> usize {
+    u32::from_le_bytes(bits[at..at + 4].try_into().unwrap()) as usize
+}
+
+fn write_u32(bits: &mut [u8], at: usize, val: usize) {
+    bits[at..at + 4].copy_from_slice(&(val as u32).to_le_bytes());
 }
 
 impl Page {
     pub fn new() -> Self {
-        Page {
-            records: Vec::new(),
-            num_records: 0,
-            current_size_bytes: 0,
+        let mut page = Page {
+            data: vec![0; PAGE_SIZE],
+            first: 0,
+            used: 0,
+            free_slots: 0,
+        };
+        page.set_data_start(PAGE_SIZE);
+        page
+    }
+
+    pub fn num_slots(&self) -> usize {
+        read_u32(&self.data, 0)
+    }
+
+    fn set_num_slots(&mut self, num_slots: usize) {
+        write_u32(&mut self.data, 0, num_slots);
+    }
+
+    // start of the record data, everything from here to the end of the page is either a record
+    // or a hole left by one
+    fn data_start(&self) -> usize {
+        read_u32(&self.data, 4)
+    }
+
+    fn set_data_start(&mut self, start: usize) {
+        write_u32(&mut self.data, 4, start);
+    }
+
+    fn set_slot(&mut self, slot: usize, offset: usize, len: usize) {
+        let at = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        write_u32(&mut self.data, at, offset);
+        write_u32(&mut self.data, at + 4, len);
+    }
+
+    pub fn get_slot(&self, slot: usize) -> Option<Slot> {
+        if slot >= self.num_slots() {
+            return None;
         }
+
+        let at = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        let (offset, len) = (read_u32(&self.data, at), read_u32(&self.data, at + 4));
+
+        Some(if len & FORWARD != 0 {
+            Slot::Forward(RecordId {
+                page: offset as u64,
+                slot: len & !FORWARD,
+            })
+        } else if len & MOVED != 0 {
+            Slot::Moved {
+                offset,
+                len: len & !MOVED,
+            }
+        } else if offset == 0 {
+            Slot::Free
+        } else {
+            Slot::Record { offset, len }
+        })
     }
 
-    pub fn to_binary(&self) -> Vec<u8> {
-        let mut buffer = Vec::with_capacity(PAGE_SIZE);
+    // Where the bytes of the record in `slot` are, if they are on this page
+    fn stored(&self, slot: usize) -> Option<(usize, usize)> {
+        match self.get_slot(slot)? {
+            Slot::Record { offset, len } | Slot::Moved { offset, len } => Some((offset, len)),
+            Slot::Free | Slot::Forward(_) => None,
+        }
+    }
 
-        for record in &self.records {
-            let record_string = record.to_bytes();
-            let record_string = String::from_utf8_lossy(&record_string);
-            buffer.extend_from_slice(record_string.as_bytes());
+    fn free_slot(&self) -> Option<usize> {
+        if self.free_slots == 0 {
+            return None;
         }
 
-        // Pad to PAGE_SIZE
-        buffer.resize(PAGE_SIZE, 0);
-        buffer
+        (0..self.num_slots()).find(|&slot| self.get_slot(slot) == Some(Slot::Free))
     }
 
-    pub fn from_binary(&mut self, bits: &[u8], schema: &Schema) -> Result<()> {
-        use std::io::Cursor;
+    // Bytes left for records along with their slots between the slot directory and the records
+    pub fn get_free_space(&self) -> usize {
+        self.data_start() - PAGE_HEADER_SIZE - self.num_slots() * SLOT_SIZE
+    }
 
-        self.records.clear();
-        self.num_records = 0;
-        self.current_size_bytes = 0;
+    // Bytes left for records along with their slots once the holes left by deleted and shrunk
+    // records are compacted away
+    pub fn get_reclaimable_space(&self) -> usize {
+        let has_free_slot = self.free_slot().is_some();
+        if !has_free_slot && self.num_slots() >= MAX_RECORDS_PER_PAGE {
+            return 0;
+        }
 
-        // Convert bytes to string and create a cursor for BufRead
-        let data_str = String::from_utf8_lossy(bits);
-        let mut cursor = Cursor::new(data_str.as_bytes());
+        // a free slot saves the record from adding one to the directory
+        let free_slot = if has_free_slot { SLOT_SIZE } else { 0 };
 
-        loop {
-            let mut record = Record::new();
-            if record.extract_next_record(schema, &mut cursor).is_some() {
-                self.current_size_bytes += record.get_size();
-                self.records.push(record);
-                self.num_records += 1;
+        self.unused_space() + free_slot
+    }
+
+    // Bytes neither the directory nor the records take up
+    fn unused_space(&self) -> usize {
+        PAGE_SIZE - PAGE_HEADER_SIZE - self.num_slots() * SLOT_SIZE - self.used
+    }
+
+    // Packs the records at the back of the page again, closing up the holes between them. The
+    // records keep their slots.
+    fn compact(&mut self) {
+        let records = (0..self.num_slots())
+            .filter_map(|slot| {
+                let (offset, len) = self.stored(slot)?;
+                Some((slot, self.data[offset..offset + len].to_vec()))
+            })
+            .collect::<Vec<_>>();
+
+        let mut start = PAGE_SIZE;
+        for (slot, bits) in records {
+            start -= bits.len();
+            self.data[start..start + bits.len()].copy_from_slice(&bits);
+
+            let moved = if let Some(Slot::Moved { .. }) = self.get_slot(slot) {
+                MOVED
             } else {
-                break;
-            }
+                0
+            };
+            self.set_slot(slot, start, bits.len() | moved);
         }
+        self.set_data_start(start);
+    }
 
-        Ok(())
+    // Finds room for `len` bytes of record data, compacting the page if that's the only way to
+    // make them fit. `new_slot` is whether the directory grows by a slot for them.
+    fn allocate(&mut self, len: usize, new_slot: bool) -> Option<usize> {
+        let directory_end = PAGE_HEADER_SIZE + (self.num_slots() + new_slot as usize) * SLOT_SIZE;
+        if self.data_start() < directory_end + len {
+            self.compact();
+        }
+        if self.data_start() < directory_end + len {
+            return None;
+        }
+
+        let offset = self.data_start() - len;
+        self.set_data_start(offset);
+        Some(offset)
     }
 
-    pub fn get_first(&mut self, record: &mut Record) -> bool {
-        if self.records.is_empty() {
+    fn insert_bits(&mut self, bits: &[u8], flags: usize) -> Option<usize> {
+        let num_slots = self.num_slots();
+        let free_slot = self.free_slot();
+        let slot = match free_slot {
+            Some(slot) => slot,
+            None if num_slots < MAX_RECORDS_PER_PAGE => num_slots,
+            None => return None,
+        };
+
+        let offset = self.allocate(bits.len(), free_slot.is_none())?;
+        self.data[offset..offset + bits.len()].copy_from_slice(bits);
+        if slot == num_slots {
+            self.set_num_slots(num_slots + 1);
+        } else {
+            self.free_slots -= 1;
+        }
+        self.set_slot(slot, offset, bits.len() | flags);
+        self.used += bits.len();
+
+        Some(slot)
+    }
+
+    // Stores `bits` in `slot`, which has to exist, in place of whatever it held. Nothing changes
+    // if they don't fit.
+    fn put(&mut self, slot: usize, bits: &[u8], flags: usize) -> bool {
+        let old = self.stored(slot);
+        if let Some((offset, len)) = old
+            && bits.len() <= len
+        {
+            // shrinking leaves a hole for the next compaction
+            self.data[offset..offset + bits.len()].copy_from_slice(bits);
+            self.set_slot(slot, offset, bits.len() | flags);
+            self.used -= len - bits.len();
+            return true;
+        }
+
+        let old_len = old.map_or(0, |(_, len)| len);
+        if bits.len() > self.unused_space() + old_len {
             return false;
         }
 
-        *record = self.records.remove(0);
-        self.num_records -= 1;
-        // Note: current_size_bytes calculation is approximate in this implementation
+        // the old bytes become a hole, so the compaction can reuse them
+        self.set_slot(slot, 0, 0);
+        self.used -= old_len;
+        let offset = self.allocate(bits.len(), false).unwrap();
+        self.data[offset..offset + bits.len()].copy_from_slice(bits);
+        self.set_slot(slot, offset, bits.len() | flags);
+        self.used += bits.len();
         true
     }
 
+    // Adds `record` to the page, reusing the slot of a deleted record if there is one. Gives
+    // the slot, or `None` if the page is full.
+    pub fn insert(&mut self, record: &Record) -> Option<usize> {
+        self.insert_bits(&record.to_binary(), 0)
+    }
+
     pub fn append(&mut self, record: Record) -> bool {
-        let record_size = record.get_size();
+        self.insert(&record).is_some()
+    }
 
-        if self.current_size_bytes + record_size + 8 > PAGE_SIZE
-            || self.records.len() >= MAX_RECORDS_PER_PAGE
-        {
+    // Replaces the record in `slot`, gives false and keeps the old one if the new one doesn't
+    // fit on the page
+    pub fn update(&mut self, slot: usize, record: &Record) -> bool {
+        matches!(self.get_slot(slot), Some(Slot::Record { .. }))
+            && self.put(slot, &record.to_binary(), 0)
+    }
+
+    // Frees `slot`, the bytes of its record get reclaimed by the next compaction
+    pub fn delete(&mut self, slot: usize) -> bool {
+        if matches!(self.get_slot(slot), None | Some(Slot::Free)) {
+            return false;
+        }
+        self.used -= self.stored(slot).map_or(0, |(_, len)| len);
+        self.set_slot(slot, 0, 0);
+        self.free_slots += 1;
+
+        // free slots at the end of the directory go away entirely
+        let mut num_slots = self.num_slots();
+        while num_slots > 0 && self.get_slot(num_slots - 1) == Some(Slot::Free) {
+            num_slots -= 1;
+            self.free_slots -= 1;
+        }
+        self.set_num_slots(num_slots);
+        if num_slots == 0 {
+            self.set_data_start(PAGE_SIZE);
+        }
+
+        true
+    }
+
+    // Puts `record` in front of the one in `slot`, moving it and the records after it up by a
+    // slot. Pages only changed this way keep their records in order, like the nodes of an index.
+    pub fn insert_at(&mut self, slot: usize, record: &Record) -> bool {
+        let num_slots = self.num_slots();
+        if slot > num_slots || num_slots >= MAX_RECORDS_PER_PAGE {
+            return false;
+        }
+
+        let bits = record.to_binary();
+        let Some(offset) = self.allocate(bits.len(), true) else {
+            return false;
+        };
+        self.data[offset..offset + bits.len()].copy_from_slice(&bits);
+
+        let at = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        let end = PAGE_HEADER_SIZE + num_slots * SLOT_SIZE;
+        self.data.copy_within(at..end, at + SLOT_SIZE);
+        self.set_num_slots(num_slots + 1);
+        self.set_slot(slot, offset, bits.len());
+        self.used += bits.len();
+
+        true
+    }
+
+    // Drops the record in `slot` and moves the ones after it down by a slot
+    pub fn remove_at(&mut self, slot: usize) -> bool {
+        let num_slots = self.num_slots();
+        if slot >= num_slots {
             return false;
         }
+        if self.get_slot(slot) == Some(Slot::Free) {
+            self.free_slots -= 1;
+        }
+        self.used -= self.stored(slot).map_or(0, |(_, len)| len);
+
+        let at = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        let end = PAGE_HEADER_SIZE + num_slots * SLOT_SIZE;
+        self.data.copy_within(at + SLOT_SIZE..end, at);
+        self.set_num_slots(num_slots - 1);
+        if num_slots == 1 {
+            self.set_data_start(PAGE_SIZE);
+        }
 
-        self.records.push(record);
-        self.num_records += 1;
-        self.current_size_bytes += record_size + 8; // +8 for overhead
         true
     }
 
+    // Bytes the slot directory and the records take up
+    pub fn get_used_space(&self) -> usize {
+        self.num_slots() * SLOT_SIZE + self.used
+    }
+
+    // Points `slot` to where its record lives now, dropping what it held
+    fn set_forward(&mut self, slot: usize, target: RecordId) {
+        self.used -= self.stored(slot).map_or(0, |(_, len)| len);
+        self.set_slot(slot, target.page as usize, target.slot | FORWARD);
+    }
+
+    pub fn to_binary(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn from_binary(&mut self, bits: &[u8]) -> Result<()> {
+        if bits.len() != PAGE_SIZE {
+            return Err(anyhow!("A page has {PAGE_SIZE} bytes, got {}", bits.len()));
+        }
+
+        self.data.copy_from_slice(bits);
+        self.validate()
+    }
+
+    // Reads page `page_num` of `file` into this page, reusing its buffer
+    pub fn read_from(&mut self, file: &mut File, page_num: u64) -> Result<()> {
+        file.seek(SeekFrom::Start(page_offset(page_num)))?;
+        if file.read_exact(&mut self.data).is_err() {
+            self.empty_it_out();
+            return Err(anyhow!("Failed to read page: reached end of file"));
+        }
+
+        self.validate()
+    }
+
+    pub fn write_to(&self, file: &mut File, page_num: u64) -> Result<()> {
+        file.seek(SeekFrom::Start(page_offset(page_num)))?;
+        file.write_all(&self.data)?;
+        file.flush()?;
+
+        Ok(())
+    }
+
+    // Checks the header and the slot directory of a page that was just read in, the page is left
+    // empty if they don't make sense
+    fn validate(&mut self) -> Result<()> {
+        self.first = 0;
+
+        let num_slots = self.num_slots();
+        let data_start = self.data_start();
+        let directory_end = PAGE_HEADER_SIZE + num_slots * SLOT_SIZE;
+        if directory_end > data_start || data_start > PAGE_SIZE {
+            self.empty_it_out();
+            return Err(anyhow!("Corrupt page header"));
+        }
+
+        if (0..num_slots).any(|slot| {
+            self.stored(slot)
+                .is_some_and(|(offset, len)| offset < data_start || offset + len > PAGE_SIZE)
+        }) {
+            self.empty_it_out();
+            return Err(anyhow!("Corrupt slot directory"));
+        }
+
+        self.used = (0..num_slots)
+            .filter_map(|slot| self.stored(slot))
+            .map(|(_, len)| len)
+            .sum();
+        self.free_slots = (0..num_slots)
+            .filter(|&slot| self.get_slot(slot) == Some(Slot::Free))
+            .count();
+
+        Ok(())
+    }
+
+    // Hands out the records stored on the page in the order of their slots. Records that moved
+    // here from another page are left out, as are the ones that moved away.
+    pub fn get_first(&mut self, record: &mut Record) -> bool {
+        while let Some(slot) = self.get_slot(self.first) {
+            self.first += 1;
+
+            if let Slot::Record { offset, len } = slot {
+                return record
+                    .from_binary(&self.data[offset..offset + len])
+                    .is_some();
+            }
+        }
+
+        false
+    }
+
     pub fn empty_it_out(&mut self) {
-        self.records.clear();
-        self.num_records = 0;
-        self.current_size_bytes = 0;
+        self.data.fill(0);
+        self.set_data_start(PAGE_SIZE);
+        self.first = 0;
+        self.used = 0;
+        self.free_slots = 0;
     }
 
     pub fn is_empty(&self) -> bool {
-        self.records.is_empty()
+        self.get_num_records() == 0
     }
 
+    // Number of records `get_first` has yet to hand out
     pub fn get_num_records(&self) -> usize {
-        self.num_records
+        (self.first..self.num_slots())
+            .filter(|&slot| matches!(self.get_slot(slot), Some(Slot::Record { .. })))
+            .count()
+    }
+
+    // The record whose bytes are in `slot`, whether it moved here or not
+    pub fn get_record(&self, slot: usize) -> Option<Record> {
+        let (offset, len) = self.stored(slot)?;
+        let mut record = Record::new();
+        record.from_binary(&self.data[offset..offset + len])?;
+        Some(record)
     }
 }
 
+impl Default for Page {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Where a record lives in its file: the page and the slot within that page. It doesn't change
+/// for as long as the record exists.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordId {
+    pub page: u64,
+    pub slot: usize,
+}
+
+impl std::fmt::Display for RecordId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}, {})", self.page, self.slot)
+    }
+}
+
+/// How many bytes every page of a file could still take. The pages are also kept ordered by
+/// that, so one with room for a record is found without going through all of them.
+#[derive(Debug, Default)]
+struct FreeSpaceMap {
+    free: Vec<usize>,
+    by_space: BTreeSet<(usize, u64)>,
+}
+
+impl FreeSpaceMap {
+    fn push(&mut self, free: usize) {
+        self.by_space.insert((free, self.free.len() as u64));
+        self.free.push(free);
+    }
+
+    fn set(&mut self, page: u64, free: usize) {
+        let old = std::mem::replace(&mut self.free[page as usize], free);
+        self.by_space.remove(&(old, page));
+        self.by_space.insert((free, page));
+    }
+
+    // The page other than `except` with the least room that still fits `needed` bytes
+    fn find(&self, needed: usize, except: Option<u64>) -> Option<u64> {
+        self.by_space
+            .range((needed, 0)..)
+            .map(|&(_, page)| page)
+            .find(|&page| Some(page) != except)
+    }
+}
+
+/// Every data file starts with a header identifying the format, followed by the pages
+const FILE_MAGIC: &[u8; 4] = b"DBF\0";
+
+/// Bumped whenever the layout of the pages changes
+const FILE_VERSION: u32 = 1;
+
+/// The magic, the version, the page size and the file type, padded to 16 bytes
+const FILE_HEADER_SIZE: u64 = 16;
+
 #[derive(Debug)]
 pub struct DBFile {
     file: Option<File>,
+    // the id the file has in the buffer pool while it's open
+    file_id: Option<usize>,
     file_name: String,
     current_page_pos: u64,
-    current_page: Page,
+    // slot on the current page `get_next` looks at next
+    current_slot: usize,
+    // pages in the file, including new ones that only are in the buffer pool so far
+    num_pages: u64,
+    // bytes every page could still take, built from the pages the first time a record gets
+    // stored after opening the file
+    free_space: Option<FreeSpaceMap>,
+    buffer_pool: SharedBufferPool,
     is_open: bool,
+    file_type: FileType,
     pub schema: Option<Schema>,
 }
 
@@ -124,19 +547,25 @@ impl DBFile {
     pub fn new() -> Self {
         DBFile {
             file: None,
+            file_id: None,
             file_name: String::new(),
             current_page_pos: 0,
-            current_page: Page::new(),
+            current_slot: 0,
+            num_pages: 0,
+            free_space: None,
+            buffer_pool: BufferPool::global(),
             is_open: false,
+            file_type: FileType::Heap,
             schema: None,
         }
     }
 
-    pub fn create<P: AsRef<Path>>(&mut self, file_path: P, _file_type: FileType) -> Result<()> {
+    pub fn create<P: AsRef<Path>>(&mut self, file_path: P, file_type: FileType) -> Result<()> {
         let path = file_path.as_ref();
+        self.close()?;
         self.file_name = path.to_string_lossy().to_string();
 
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .create(true)
             .write(true)
             .read(true)
@@ -144,9 +573,20 @@ impl DBFile {
             .open(path)
             .map_err(|e| anyhow!("Failed to create file {:?}: {:?}", path, e))?;
 
+        let mut header = [0u8; FILE_HEADER_SIZE as usize];
+        header[0..4].copy_from_slice(FILE_MAGIC);
+        header[4..8].copy_from_slice(&FILE_VERSION.to_le_bytes());
+        header[8..12].copy_from_slice(&(PAGE_SIZE as u32).to_le_bytes());
+        header[12] = file_type as u8;
+        file.write_all(&header)?;
+
+        self.file_id = Some(lock_buffer_pool(&self.buffer_pool).register_file(file.try_clone()?));
         self.file = Some(file);
+        self.file_type = file_type;
         self.current_page_pos = 0;
-        self.current_page = Page::new();
+        self.current_slot = 0;
+        self.num_pages = 0;
+        self.free_space = Some(FreeSpaceMap::default());
         self.is_open = true;
 
         Ok(())
@@ -154,17 +594,39 @@ impl DBFile {
 
     pub fn open<P: AsRef<Path>>(&mut self, file_path: P) -> Result<()> {
         let path = file_path.as_ref();
+        self.close()?;
         self.file_name = path.to_string_lossy().to_string();
 
-        let file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .open(path)
             .map_err(|e| anyhow!("Failed to open file {:?}: {:?}", path, e))?;
 
+        let mut header = [0u8; FILE_HEADER_SIZE as usize];
+        if file.read_exact(&mut header).is_err() || &header[0..4] != FILE_MAGIC {
+            return Err(anyhow!("{:?} is not a data file", path));
+        }
+        let version = u32::from_le_bytes(header[4..8].try_into().unwrap());
+        let page_size = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != FILE_VERSION || page_size as usize != PAGE_SIZE {
+            return Err(anyhow!(
+                "{:?} has version {version} with {page_size} byte pages, expected version {FILE_VERSION} with {PAGE_SIZE} byte pages",
+                path
+            ));
+        }
+        self.file_type = match header[12] {
+            0 => FileType::Heap,
+            1 => FileType::Sorted,
+            2 => FileType::Index,
+            other => return Err(anyhow!("{:?} has unknown file type {other}", path)),
+        };
+
+        let len = file.metadata()?.len();
+        self.num_pages = len.saturating_sub(FILE_HEADER_SIZE) / PAGE_SIZE as u64;
+        self.file_id = Some(lock_buffer_pool(&self.buffer_pool).register_file(file.try_clone()?));
         self.file = Some(file);
-        self.current_page_pos = 0;
-        self.current_page = Page::new();
+        self.free_space = None;
         self.is_open = true;
 
         self.move_first();
@@ -172,109 +634,278 @@ impl DBFile {
         Ok(())
     }
 
+    // Writes back the pages of the file that changed and drops them from the buffer pool
     pub fn close(&mut self) -> Result<()> {
-        if self.file.is_some() {
-            if !self.current_page.is_empty() {
-                self.write_current_page()?;
-            }
-            self.file.take();
+        if let Some(file_id) = self.file_id.take() {
+            lock_buffer_pool(&self.buffer_pool).close_file(file_id)?;
         }
+        self.file.take();
         self.is_open = false;
         Ok(())
     }
 
+    // Moves the file to another buffer pool, the pages it had in the old one get written back
+    pub fn set_buffer_pool(&mut self, buffer_pool: SharedBufferPool) -> Result<()> {
+        if let Some(file_id) = self.file_id.take() {
+            lock_buffer_pool(&self.buffer_pool).close_file(file_id)?;
+        }
+        self.buffer_pool = buffer_pool;
+
+        if let Some(file) = &self.file {
+            self.file_id =
+                Some(lock_buffer_pool(&self.buffer_pool).register_file(file.try_clone()?));
+        }
+
+        Ok(())
+    }
+
+    pub fn get_buffer_pool(&self) -> &SharedBufferPool {
+        &self.buffer_pool
+    }
+
     pub fn move_first(&mut self) {
         self.current_page_pos = 0;
-        if self.schema.is_some() {
-            if let Err(_) = self.load_page(0) {
-                self.current_page = Page::new();
+        self.current_slot = 0;
+    }
+
+    pub fn get_next(&mut self, record: &mut Record) -> Result<bool> {
+        Ok(self.get_next_with_id(record)?.is_some())
+    }
+
+    // Same as `get_next`, but also gives where the record is stored. Records that moved to
+    // another page come up where they were first stored.
+    pub fn get_next_with_id(&mut self, record: &mut Record) -> Result<Option<RecordId>> {
+        while self.current_page_pos < self.num_pages {
+            let rid = RecordId {
+                page: self.current_page_pos,
+                slot: self.current_slot,
+            };
+
+            let (slot, stored) = self.with_page(rid.page, |page| {
+                (page.get_slot(rid.slot), page.get_record(rid.slot))
+            })?;
+            match slot {
+                Some(Slot::Record { .. }) => {
+                    self.current_slot += 1;
+                    *record = stored.ok_or_else(|| anyhow!("Corrupt record at {rid}"))?;
+                    return Ok(Some(rid));
+                }
+                Some(Slot::Forward(_)) => {
+                    self.current_slot += 1;
+                    *record = self.get_record(rid)?;
+                    return Ok(Some(rid));
+                }
+                Some(Slot::Free) | Some(Slot::Moved { .. }) => self.current_slot += 1,
+                // the scan stays on the last page, so it sees records appended to it later on
+                None if rid.page + 1 == self.num_pages => return Ok(None),
+                None => {
+                    self.current_page_pos += 1;
+                    self.current_slot = 0;
+                }
             }
-        } else {
-            self.current_page = Page::new();
         }
+
+        Ok(None)
     }
 
-    pub fn get_next(&mut self, record: &mut Record) -> Result<bool> {
-        if self.current_page.get_first(record) {
-            return Ok(true);
+    // Fetches the record stored at `rid` without moving the scan
+    pub fn get_record(&mut self, rid: RecordId) -> Result<Record> {
+        if rid.page >= self.num_pages {
+            return Err(anyhow!("No record at {rid} in {}", self.file_name));
         }
 
-        self.current_page_pos += 1;
-        if let Ok(()) = self.load_page(self.current_page_pos) {
-            if self.current_page.get_first(record) {
-                return Ok(true);
+        let (slot, stored) = self.with_page(rid.page, |page| {
+            (page.get_slot(rid.slot), page.get_record(rid.slot))
+        })?;
+        let record = match slot {
+            Some(Slot::Record { .. }) => stored,
+            Some(Slot::Forward(target)) => {
+                self.with_page(target.page, |page| page.get_record(target.slot))?
             }
+            _ => None,
+        };
+
+        record.ok_or_else(|| anyhow!("No record at {rid} in {}", self.file_name))
+    }
+
+    // Stores `record` on a page with room for it, or on a new page at the end of the file.
+    // Appending while scanning moves the scan on to the page the record went to if that's past
+    // the one being scanned, records that fill a hole on an earlier page aren't seen by the scan.
+    pub fn append_record(&mut self, record: Record) -> Result<RecordId> {
+        let rid = self.insert(&record.to_binary(), 0, None)?;
+        if rid.page > self.current_page_pos {
+            self.current_page_pos = rid.page;
+            self.current_slot = 0;
         }
 
-        Ok(false)
+        Ok(rid)
     }
 
-    pub fn append_record(&mut self, record: Record) -> Result<()> {
-        if !self.current_page.append(record.clone()) {
-            self.write_current_page()?;
-            self.current_page_pos += 1;
-            self.current_page = Page::new();
+    // Replaces the record at `rid`. If it doesn't fit on its page anymore, it moves to another
+    // one and its slot forwards to it, so `rid` stays valid.
+    pub fn update(&mut self, rid: RecordId, record: Record) -> Result<()> {
+        let moved_to = match self.get_slot(rid)? {
+            Some(Slot::Record { .. }) => None,
+            Some(Slot::Forward(target)) => Some(target),
+            _ => return Err(anyhow!("No record at {rid} in {}", self.file_name)),
+        };
+
+        let bits = record.to_binary();
+        if bits.len() > MAX_RECORD_SIZE {
+            return Err(anyhow!(
+                "Record of {} bytes doesn't fit on a page of {}",
+                bits.len(),
+                self.file_name
+            ));
+        }
+
+        // The new copy is stored before the one it moved to gets deleted, so the record is left
+        // as it was if that fails. It goes back to its own page if it fits there again.
+        if !self.modify_page(rid.page, |page| page.put(rid.slot, &bits, 0))? {
+            let target = self.insert(&bits, MOVED, Some(rid.page))?;
+            self.modify_page(rid.page, |page| page.set_forward(rid.slot, target))?;
+        }
 
-            if !self.current_page.append(record) {
-                return Err(anyhow!("failled to append record to new page"));
+        if let Some(target) = moved_to {
+            self.modify_page(target.page, |page| page.delete(target.slot))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delete(&mut self, rid: RecordId) -> Result<()> {
+        match self.get_slot(rid)? {
+            Some(Slot::Record { .. }) => (),
+            Some(Slot::Forward(target)) => {
+                self.modify_page(target.page, |page| page.delete(target.slot))?;
             }
+            _ => return Err(anyhow!("No record at {rid} in {}", self.file_name)),
         }
+
+        self.modify_page(rid.page, |page| page.delete(rid.slot))?;
         Ok(())
     }
 
-    pub fn load(&mut self, schema: &Schema, text_file_path: &str) -> Result<()> {
-        self.schema = Some(schema.clone());
+    fn get_slot(&self, rid: RecordId) -> Result<Option<Slot>> {
+        if rid.page >= self.num_pages {
+            return Ok(None);
+        }
 
-        let file = std::fs::File::open(text_file_path)?;
-        let mut reader = BufReader::new(file);
+        self.with_page(rid.page, |page| page.get_slot(rid.slot))
+    }
 
-        self.current_page_pos = 0;
-        self.current_page = Page::new();
+    // Stores `bits` on the page other than `except` that has the least room that fits them, or
+    // on a new page at the end of the file
+    fn insert(&mut self, bits: &[u8], flags: usize, except: Option<u64>) -> Result<RecordId> {
+        if self.file_type == FileType::Index {
+            return Err(anyhow!(
+                "{} is an index, its pages only change through a BTreeIndex",
+                self.file_name
+            ));
+        }
+        if bits.len() > MAX_RECORD_SIZE {
+            return Err(anyhow!("failled to append record to new page"));
+        }
+        let needed = bits.len() + SLOT_SIZE;
 
-        let mut record = Record::new();
-        while record.extract_next_record(schema, &mut reader).is_some() {
-            self.append_record(record.clone())?;
-            record = Record::new();
+        let page = self.free_space_map()?.find(needed, except);
+        let page = match page {
+            Some(page) => page,
+            None => self.new_page()?,
+        };
+
+        let slot = self.modify_page(page, |page| page.insert_bits(bits, flags))?;
+        let slot = slot.ok_or_else(|| anyhow!("Page {page} of {} is full", self.file_name))?;
+
+        Ok(RecordId { page, slot })
+    }
+
+    // Adds an empty page at the end of the file and gives its number
+    pub fn new_page(&mut self) -> Result<u64> {
+        let page = self.num_pages;
+        let file_id = self.file_id.ok_or(anyhow!("DBFile.file is None"))?;
+        let mut buffer_pool = lock_buffer_pool(&self.buffer_pool);
+        let frame = buffer_pool.pin_new(file_id, page)?;
+        buffer_pool.unpin(frame, true);
+        drop(buffer_pool);
+
+        self.num_pages += 1;
+        if let Some(free_space) = self.free_space.as_mut() {
+            free_space.push(PAGE_SIZE - PAGE_HEADER_SIZE);
         }
 
-        if !self.current_page.is_empty() {
-            self.write_current_page()?;
+        Ok(page)
+    }
+
+    fn free_space_map(&mut self) -> Result<&mut FreeSpaceMap> {
+        if self.free_space.is_none() {
+            let mut free_space = FreeSpaceMap::default();
+            for page in 0..self.num_pages {
+                free_space.push(self.with_page(page, Page::get_reclaimable_space)?);
+            }
+
+            self.free_space = Some(free_space);
         }
 
-        Ok(())
+        Ok(self.free_space.as_mut().unwrap())
     }
 
-    fn load_page(&mut self, page_num: u64) -> Result<()> {
-        let file = self.file.as_mut().ok_or(anyhow!("DBFile.page is None"))?;
-        let schema = self
-            .schema
-            .as_ref()
-            .ok_or(anyhow!("DBFile.schema is None"))?;
+    // Runs `f` on page `page_num`, which stays pinned in the buffer pool meanwhile
+    pub fn with_page<T>(&self, page_num: u64, f: impl FnOnce(&Page) -> T) -> Result<T> {
+        let file_id = self.file_id.ok_or(anyhow!("DBFile.file is None"))?;
+        let mut buffer_pool = lock_buffer_pool(&self.buffer_pool);
 
-        file.seek(SeekFrom::Start(page_num * PAGE_SIZE as u64))?;
+        let frame = buffer_pool.pin(file_id, page_num)?;
+        let result = f(buffer_pool.get_page(frame));
+        buffer_pool.unpin(frame, false);
 
-        let mut buffer = vec![0u8; PAGE_SIZE];
-        let bytes_read = file.read(&mut buffer)?;
+        Ok(result)
+    }
 
-        if bytes_read == 0 {
-            self.current_page = Page::new();
-            return Err(anyhow!("Failed to read page: reached end of file"));
+    // Runs `f` on page `page_num` and keeps the free space map up to date with the result. The
+    // page gets written back once the buffer pool evicts it or the file is closed.
+    pub fn modify_page<T>(&mut self, page_num: u64, f: impl FnOnce(&mut Page) -> T) -> Result<T> {
+        let file_id = self.file_id.ok_or(anyhow!("DBFile.file is None"))?;
+        let mut buffer_pool = lock_buffer_pool(&self.buffer_pool);
+
+        let frame = buffer_pool.pin(file_id, page_num)?;
+        let page = buffer_pool.get_page_mut(frame);
+        let result = f(page);
+        let free = page.get_reclaimable_space();
+        buffer_pool.unpin(frame, true);
+        drop(buffer_pool);
+
+        if let Some(free_space) = self.free_space.as_mut() {
+            free_space.set(page_num, free);
         }
 
-        self.current_page.from_binary(&buffer, schema)?;
-        Ok(())
+        Ok(result)
     }
 
-    fn write_current_page(&mut self) -> Result<()> {
-        let file = self.file.as_mut().ok_or(anyhow!(""))?;
+    // Number of pages in the file, counting new ones that aren't written to disk yet
+    pub fn get_num_pages(&self) -> u64 {
+        self.num_pages
+    }
 
-        file.seek(SeekFrom::Start(self.current_page_pos * PAGE_SIZE as u64))?;
+    // Writes the pages that changed to disk
+    pub fn flush(&mut self) -> Result<()> {
+        let file_id = self.file_id.ok_or(anyhow!("DBFile.file is None"))?;
+        lock_buffer_pool(&self.buffer_pool).flush_file(file_id)
+    }
 
-        let page_data = self.current_page.to_binary();
-        file.write_all(&page_data)?;
-        file.flush()?;
+    pub fn load(&mut self, schema: &Schema, text_file_path: &str) -> Result<()> {
+        self.schema = Some(schema.clone());
 
-        Ok(())
+        let file = std::fs::File::open(text_file_path)?;
+        let mut reader = BufReader::new(file);
+
+        let mut record = Record::new();
+        while record.extract_next_record(schema, &mut reader).is_some() {
+            self.append_record(record.clone())?;
+            record = Record::new();
+        }
+
+        self.flush()
     }
 
     pub fn get_file_name(&self) -> &str {
@@ -289,8 +920,28 @@ impl DBFile {
         self.current_page_pos
     }
 
+    // Number of records `get_next` has yet to hand out from the current page
     pub fn get_current_page_record_count(&self) -> usize {
-        self.current_page.get_num_records()
+        if self.current_page_pos >= self.num_pages {
+            return 0;
+        }
+
+        let first = self.current_slot;
+        self.with_page(self.current_page_pos, |page| {
+            (first..page.num_slots())
+                .filter(|&slot| {
+                    matches!(
+                        page.get_slot(slot),
+                        Some(Slot::Record { .. }) | Some(Slot::Forward(_))
+                    )
+                })
+                .count()
+        })
+        .unwrap_or(0)
+    }
+
+    pub fn get_file_type(&self) -> FileType {
+        self.file_type
     }
 
     pub fn set_schema(&mut self, schema: Schema) {
@@ -301,6 +952,11 @@ impl DBFile {
     }
 }
 
+// Where page `page_num` starts in the file
+fn page_offset(page_num: u64) -> u64 {
+    FILE_HEADER_SIZE + page_num * PAGE_SIZE as u64
+}
+
 impl Default for DBFile {
     fn default() -> Self {
         Self::new()
@@ -327,7 +983,7 @@ mod tests {
             "Integer".to_string(),
         ];
         let distincts = vec![0, 0, 0];
-        Schema::new(&attributes, &types, &distincts, 0, "test.tbl")
+        Schema::new(&attributes, &types, &distincts, 0, "test.tbl".to_string())
     }
 
     fn create_test_record() -> Record {
@@ -354,8 +1010,7 @@ mod tests {
         let page = Page::new();
         assert!(page.is_empty());
         assert_eq!(page.get_num_records(), 0);
-        assert_eq!(page.current_size_bytes, 0);
-        assert_eq!(page.records.len(), 0);
+        assert_eq!(page.get_free_space(), PAGE_SIZE - PAGE_HEADER_SIZE);
     }
 
     #[test]
@@ -363,10 +1018,15 @@ mod tests {
         let mut page = Page::new();
         let record = create_test_record();
 
+        let size = record.to_binary().len();
+
         assert!(page.append(record));
         assert!(!page.is_empty());
         assert_eq!(page.get_num_records(), 1);
-        assert_eq!(page.records.len(), 1);
+        assert_eq!(
+            page.get_free_space(),
+            PAGE_SIZE - PAGE_HEADER_SIZE - SLOT_SIZE - size
+        );
     }
 
     #[test]
@@ -395,6 +1055,7 @@ mod tests {
 
         let mut retrieved_record = Record::new();
         assert!(page.get_first(&mut retrieved_record));
+        assert_eq!(retrieved_record, original_record);
         assert!(page.is_empty());
         assert_eq!(page.get_num_records(), 0);
     }
@@ -427,7 +1088,7 @@ mod tests {
 
         assert!(page.is_empty());
         assert_eq!(page.get_num_records(), 0);
-        assert_eq!(page.current_size_bytes, 0);
+        assert_eq!(page.get_free_space(), PAGE_SIZE - PAGE_HEADER_SIZE);
     }
 
     #[test]
@@ -439,23 +1100,36 @@ mod tests {
         let binary = page.to_binary();
         assert_eq!(binary.len(), PAGE_SIZE);
 
-        let data_str = String::from_utf8_lossy(&binary);
-        assert!(data_str.contains("42|John Doe|25|"));
+        // numbers are stored as binary, not as text
+        assert_eq!(read_u32(binary, 0), 1);
+        assert!(binary.windows(8).any(|bits| bits == 42i64.to_le_bytes()));
+        assert!(binary.windows(8).any(|bits| bits == b"John Doe"));
+        assert!(!binary.windows(3).any(|bits| bits == b"42|"));
     }
 
     #[test]
     fn test_page_from_binary() {
-        let schema = create_test_schema();
-
-        let test_data = "1|Alice|30|\n2|Bob|25|\n";
-        let mut binary = test_data.as_bytes().to_vec();
-        binary.resize(PAGE_SIZE, 0); // Pad to page size
+        let mut binary = vec![0u8; PAGE_SIZE];
+        write_u32(&mut binary, 4, PAGE_SIZE);
 
         let mut page = Page::new();
-        assert!(page.from_binary(&binary, &schema).is_ok());
+        assert!(page.from_binary(&binary).is_ok());
+        assert!(page.is_empty());
 
-        assert!(!page.is_empty());
-        assert_eq!(page.get_num_records(), 2);
+        // pipe-delimited text from before the binary pages
+        let mut text = "1|Alice|30|\n2|Bob|25|\n".as_bytes().to_vec();
+        text.resize(PAGE_SIZE, 0);
+        assert!(page.from_binary(&text).is_err());
+
+        // slots pointing outside of the page
+        write_u32(&mut binary, 0, 1);
+        write_u32(&mut binary, 4, PAGE_SIZE - 4);
+        write_u32(&mut binary, PAGE_HEADER_SIZE, PAGE_SIZE - 4);
+        write_u32(&mut binary, PAGE_HEADER_SIZE + 4, 8);
+        assert!(page.from_binary(&binary).is_err());
+        assert!(page.is_empty());
+
+        assert!(page.from_binary(&binary[..100]).is_err());
     }
 
     #[test]
@@ -475,16 +1149,27 @@ mod tests {
         original_page.append(record2);
         assert_eq!(original_page.get_num_records(), 2);
 
-        let binary = original_page.to_binary();
+        let mut record3 = Record::new();
+        record3.push_flt(2.5);
+        record3.push_null();
+        record3.push_str("");
+        original_page.append(record3.clone());
 
-        let string_data = String::from_utf8_lossy(&binary);
-        println!("Serialized Page Data:\n{}", string_data);
+        let binary = original_page.to_binary();
 
         let mut restored_page = Page::new();
-        assert!(restored_page.from_binary(&binary, &schema).is_ok());
+        assert!(restored_page.from_binary(binary).is_ok());
 
-        assert_eq!(restored_page.get_num_records(), 2);
+        assert_eq!(restored_page.get_num_records(), 3);
         assert!(!restored_page.is_empty());
+
+        let mut record = Record::new();
+        let mut rows = Vec::new();
+        while restored_page.get_first(&mut record) {
+            rows.push(String::from_utf8(record.to_bytes()).unwrap());
+        }
+        assert_eq!(rows, vec!["1|Alice|30|\n", "2|Bob|25|\n", "2.5|||\n"]);
+        assert_eq!(record, record3);
     }
 
     #[test]
@@ -703,6 +1388,405 @@ mod tests {
         assert!(append_count <= MAX_RECORDS_PER_PAGE);
     }
 
+    #[test]
+    fn test_dbfile_file_header() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path();
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.create(&file_path, FileType::Sorted).is_ok());
+        assert!(db_file.close().is_ok());
+        assert_eq!(
+            std::fs::metadata(file_path).unwrap().len(),
+            FILE_HEADER_SIZE
+        );
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+        assert_eq!(db_file.get_file_type(), FileType::Sorted);
+        assert!(db_file.close().is_ok());
+
+        // files in another format or from another version don't get read as pages
+        let text_file = create_test_data_file();
+        assert!(DBFile::new().open(text_file.path()).is_err());
+
+        let mut bits = std::fs::read(file_path).unwrap();
+        bits[4] = 2;
+        std::fs::write(file_path, &bits).unwrap();
+        assert!(DBFile::new().open(&file_path).is_err());
+    }
+
+    #[test]
+    fn test_dbfile_read_back_multiple_pages() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path();
+        let schema = create_test_schema();
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.create(&file_path, FileType::Heap).is_ok());
+        db_file.set_schema(schema.clone());
+
+        let count = 2 * MAX_RECORDS_PER_PAGE + 10;
+        for i in 0..count {
+            let mut record = Record::new();
+            record.push_int(i as i64);
+            record.push_str(&format!("User{i}"));
+            record.push_int(20 + (i % 50) as i64);
+            assert!(db_file.append_record(record).is_ok());
+        }
+        assert!(db_file.close().is_ok());
+        assert_eq!(
+            std::fs::metadata(file_path).unwrap().len(),
+            FILE_HEADER_SIZE + 3 * PAGE_SIZE as u64
+        );
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+        db_file.set_schema(schema);
+
+        let mut record = Record::new();
+        let mut read = 0;
+        while db_file.get_next(&mut record).unwrap() {
+            assert_eq!(record.get_column(0), Some(MappedAttrData::Integer(read)));
+            assert_eq!(
+                record.get_column(1),
+                Some(MappedAttrData::String(&format!("User{read}")))
+            );
+            read += 1;
+        }
+        assert_eq!(read as usize, count);
+    }
+
+    #[test]
+    fn test_dbfile_record_ids() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path();
+
+        let user = |i: usize| {
+            let mut record = Record::new();
+            record.push_int(i as i64);
+            record.push_str(&format!("User{i}"));
+            record.push_int(25);
+            record
+        };
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.create(&file_path, FileType::Heap).is_ok());
+
+        let count = MAX_RECORDS_PER_PAGE + 10;
+        let rids = (0..count)
+            .map(|i| db_file.append_record(user(i)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rids[0], RecordId { page: 0, slot: 0 });
+        assert_eq!(rids[MAX_RECORDS_PER_PAGE - 1].page, 0);
+        assert_eq!(rids[MAX_RECORDS_PER_PAGE], RecordId { page: 1, slot: 0 });
+
+        // from the page on disk as well as from the one that's still in memory
+        assert_eq!(db_file.get_record(rids[5]).unwrap(), user(5));
+        assert_eq!(
+            db_file.get_record(rids[count - 1]).unwrap(),
+            user(count - 1)
+        );
+        assert!(db_file.get_record(RecordId { page: 1, slot: 10 }).is_err());
+        assert!(db_file.get_record(RecordId { page: 7, slot: 0 }).is_err());
+        assert!(db_file.close().is_ok());
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+
+        let mut record = Record::new();
+        let mut scanned = Vec::new();
+        for _ in 0..3 {
+            scanned.push(db_file.get_next_with_id(&mut record).unwrap().unwrap());
+        }
+
+        // looking up a record in between doesn't move the scan
+        assert_eq!(
+            db_file.get_record(rids[count - 3]).unwrap(),
+            user(count - 3)
+        );
+        while let Some(rid) = db_file.get_next_with_id(&mut record).unwrap() {
+            assert_eq!(record, user(scanned.len()));
+            scanned.push(rid);
+        }
+        assert_eq!(scanned, rids);
+
+        // appending after a scan goes to the end of the file instead of over the first page
+        db_file.move_first();
+        let rid = db_file.append_record(user(count)).unwrap();
+        assert_eq!(rid, RecordId { page: 1, slot: 10 });
+        assert!(db_file.close().is_ok());
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+        assert_eq!(db_file.get_num_pages(), 2);
+        assert_eq!(db_file.get_record(rids[0]).unwrap(), user(0));
+        assert_eq!(db_file.get_record(rid).unwrap(), user(count));
+    }
+
+    fn padded_user(i: usize, padding: usize) -> Record {
+        let mut record = Record::new();
+        record.push_int(i as i64);
+        record.push_str(&"x".repeat(padding));
+        record
+    }
+
+    #[test]
+    fn test_page_delete_and_update() {
+        let mut page = Page::new();
+        for i in 0..4 {
+            assert_eq!(page.insert(&padded_user(i, 10)), Some(i));
+        }
+
+        assert!(page.delete(1));
+        assert!(!page.delete(1));
+        assert_eq!(page.get_slot(1), Some(Slot::Free));
+        assert_eq!(page.get_record(1), None);
+
+        // the freed slot gets reused, the others keep theirs
+        assert_eq!(page.insert(&padded_user(4, 10)), Some(1));
+        assert_eq!(page.get_record(2), Some(padded_user(2, 10)));
+
+        assert!(page.update(0, &padded_user(0, 2)));
+        assert!(page.update(2, &padded_user(2, 100)));
+        assert_eq!(page.get_record(0), Some(padded_user(0, 2)));
+        assert_eq!(page.get_record(2), Some(padded_user(2, 100)));
+        assert!(!page.update(7, &padded_user(7, 0)));
+
+        // free slots at the end are dropped from the directory
+        assert!(page.delete(3));
+        assert_eq!(page.get_slot(3), None);
+
+        let mut record = Record::new();
+        let mut ids = Vec::new();
+        while page.get_first(&mut record) {
+            ids.push(record.get_projected_data(&[0]));
+        }
+        assert_eq!(
+            ids.concat(),
+            vec![
+                ProjectedData::Integer(0),
+                ProjectedData::Integer(4),
+                ProjectedData::Integer(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_page_reuses_holes() {
+        let mut page = Page::new();
+        let mut slots = Vec::new();
+        while let Some(slot) = page.insert(&padded_user(slots.len(), 1000)) {
+            slots.push(slot);
+        }
+        assert!(page.get_free_space() < 1000);
+        assert!(!page.update(0, &padded_user(0, 2000)));
+
+        // deleting leaves holes in between the records, which only a compaction makes usable
+        for slot in slots.iter().step_by(2) {
+            assert!(page.delete(*slot));
+        }
+        assert!(page.get_free_space() < 1000);
+        assert!(page.get_reclaimable_space() > 50 * 1000);
+
+        assert!(page.update(1, &padded_user(1, 5000)));
+        assert_eq!(page.insert(&padded_user(99, 3000)), Some(0));
+        assert_eq!(page.get_record(1), Some(padded_user(1, 5000)));
+        assert_eq!(page.get_record(0), Some(padded_user(99, 3000)));
+        assert_eq!(page.get_record(3), Some(padded_user(3, 1000)));
+
+        let mut restored = Page::new();
+        assert!(restored.from_binary(page.to_binary()).is_ok());
+        assert_eq!(
+            restored.get_reclaimable_space(),
+            page.get_reclaimable_space()
+        );
+    }
+
+    #[test]
+    fn test_page_insert_at_and_remove_at() {
+        let mut page = Page::new();
+        for i in [1, 3] {
+            assert!(page.insert_at(page.num_slots(), &padded_user(i, 10)));
+        }
+        assert!(page.insert_at(0, &padded_user(0, 10)));
+        assert!(page.insert_at(2, &padded_user(2, 10)));
+        assert!(!page.insert_at(5, &padded_user(5, 10)));
+        for i in 0..4 {
+            assert_eq!(page.get_record(i), Some(padded_user(i, 10)));
+        }
+        let used = page.get_used_space();
+
+        assert!(page.remove_at(1));
+        assert!(!page.remove_at(3));
+        assert_eq!(page.num_slots(), 3);
+        assert_eq!(page.get_record(1), Some(padded_user(2, 10)));
+        assert_eq!(
+            page.get_used_space(),
+            used - SLOT_SIZE - padded_user(1, 10).to_binary().len()
+        );
+
+        // a record that doesn't fit anymore leaves the page as it was
+        assert!(page.insert_at(1, &padded_user(1, PAGE_SIZE / 2)));
+        assert!(!page.insert_at(1, &padded_user(1, PAGE_SIZE / 2)));
+        assert_eq!(page.get_record(3), Some(padded_user(3, 10)));
+
+        for _ in 0..4 {
+            assert!(page.remove_at(0));
+        }
+        assert!(page.is_empty());
+        assert_eq!(page.get_used_space(), 0);
+    }
+
+    #[test]
+    fn test_dbfile_delete_and_update() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let file_path = temp_file.path();
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.create(&file_path, FileType::Heap).is_ok());
+
+        // two pages that are almost full
+        let rids = (0..200)
+            .map(|i| db_file.append_record(padded_user(i, 1200)).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(rids[199].page, 1);
+
+        db_file.delete(rids[3]).unwrap();
+        assert!(db_file.delete(rids[3]).is_err());
+        assert!(db_file.get_record(rids[3]).is_err());
+        db_file.update(rids[4], padded_user(1004, 10)).unwrap();
+
+        // too big for the first page, so it moves to another one
+        db_file.update(rids[5], padded_user(1005, 20000)).unwrap();
+        assert_eq!(
+            db_file.get_record(rids[5]).unwrap(),
+            padded_user(1005, 20000)
+        );
+        assert!(db_file.close().is_ok());
+
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+        assert_eq!(db_file.get_record(rids[4]).unwrap(), padded_user(1004, 10));
+        assert_eq!(
+            db_file.get_record(rids[5]).unwrap(),
+            padded_user(1005, 20000)
+        );
+
+        // every record comes up once, under the id it was stored with
+        let mut record = Record::new();
+        let mut scanned = Vec::new();
+        while let Some(rid) = db_file.get_next_with_id(&mut record).unwrap() {
+            scanned.push((rid, record.get_projected_data(&[0])));
+        }
+        assert_eq!(scanned.len(), 199);
+        assert!(scanned.contains(&(rids[5], vec![ProjectedData::Integer(1005)])));
+        assert!(scanned.contains(&(rids[4], vec![ProjectedData::Integer(1004)])));
+
+        // shrinking it again brings it back to its own page
+        db_file.update(rids[5], padded_user(5, 10)).unwrap();
+        assert_eq!(db_file.get_record(rids[5]).unwrap(), padded_user(5, 10));
+        db_file.update(rids[6], padded_user(1006, 20000)).unwrap();
+        db_file.delete(rids[6]).unwrap();
+        assert!(db_file.get_record(rids[6]).is_err());
+
+        // new records fill up the holes on the first page, taking the slot of the deleted one
+        let rid = db_file.append_record(padded_user(200, 1200)).unwrap();
+        assert_eq!(rid, rids[3]);
+        assert!(db_file.close().is_ok());
+
+        // the page the big records went to stays around for the next appends
+        let mut db_file = DBFile::new();
+        assert!(db_file.open(&file_path).is_ok());
+        assert_eq!(db_file.get_num_pages(), 3);
+        assert_eq!(
+            db_file.append_record(padded_user(201, 60000)).unwrap().page,
+            2
+        );
+
+        db_file.move_first();
+        let mut count = 0;
+        while db_file.get_next(&mut record).unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 200);
+    }
+
+    #[test]
+    fn test_dbfile_failed_update_keeps_record() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut db_file = DBFile::new();
+        db_file.create(temp_file.path(), FileType::Heap).unwrap();
+
+        let rids = (0..200)
+            .map(|i| db_file.append_record(padded_user(i, 1200)).unwrap())
+            .collect::<Vec<_>>();
+        db_file.update(rids[5], padded_user(1005, 20000)).unwrap();
+
+        // the record moved to another page, which it has to keep when the update fails
+        assert!(db_file.update(rids[5], padded_user(1005, 200_000)).is_err());
+        assert!(db_file.update(rids[6], padded_user(1006, 200_000)).is_err());
+        assert_eq!(
+            db_file.get_record(rids[5]).unwrap(),
+            padded_user(1005, 20000)
+        );
+        assert_eq!(db_file.get_record(rids[6]).unwrap(), padded_user(6, 1200));
+
+        // so later appends don't end up in its slot
+        let rid = db_file.append_record(padded_user(300, 20000)).unwrap();
+        assert_eq!(
+            db_file.get_record(rids[5]).unwrap(),
+            padded_user(1005, 20000)
+        );
+        db_file.update(rids[5], padded_user(1005, 30000)).unwrap();
+        assert_eq!(db_file.get_record(rid).unwrap(), padded_user(300, 20000));
+        assert_eq!(
+            db_file.get_record(rids[5]).unwrap(),
+            padded_user(1005, 30000)
+        );
+
+        db_file.move_first();
+        let mut record = Record::new();
+        let mut count = 0;
+        while db_file.get_next(&mut record).unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 201);
+    }
+
+    #[test]
+    fn test_dbfile_append_while_scanning() {
+        let temp_file = NamedTempFile::new().unwrap();
+        let mut db_file = DBFile::new();
+        db_file.create(temp_file.path(), FileType::Heap).unwrap();
+
+        let rids = (0..200)
+            .map(|i| db_file.append_record(padded_user(i, 1200)).unwrap())
+            .collect::<Vec<_>>();
+        db_file.delete(rids[3]).unwrap();
+        db_file.delete(rids[4]).unwrap();
+
+        // scan up to the second page, then fill the hole on the first one
+        db_file.move_first();
+        let mut record = Record::new();
+        let mut scanned = Vec::new();
+        while let Some(rid) = db_file.get_next_with_id(&mut record).unwrap() {
+            scanned.push(rid);
+            if rid.page == 1 {
+                break;
+            }
+        }
+        let rid = db_file.append_record(padded_user(200, 1200)).unwrap();
+        assert_eq!(rid.page, 0);
+
+        // the scan goes on where it was, without coming across the first page again
+        while let Some(rid) = db_file.get_next_with_id(&mut record).unwrap() {
+            scanned.push(rid);
+        }
+        assert_eq!(scanned.len(), 198);
+        assert!(!scanned.contains(&rid));
+    }
+
     #[test]
     fn test_dbfile_drop_cleanup() {
         let temp_file = NamedTempFile::new().unwrap();
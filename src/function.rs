@@ -26,7 +26,7 @@ enum OpCode {
     FltMul,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ArithExpr {
     IntLit(i64),
     FltLit(f64),
@@ -39,7 +39,51 @@ pub enum ArithExpr {
     Mul(Box<ArithExpr>, Box<ArithExpr>),
 }
 
+impl std::fmt::Display for ArithExpr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // operands that are operations themselves get parenthesized so the string is unambiguous
+        fn operand(expr: &ArithExpr) -> String {
+            match expr {
+                ArithExpr::IntLit(_) | ArithExpr::FltLit(_) | ArithExpr::Load(_) => {
+                    expr.to_string()
+                }
+                _ => format!("({expr})"),
+            }
+        }
+
+        match self {
+            ArithExpr::IntLit(i) => write!(f, "{i}"),
+            ArithExpr::FltLit(x) => write!(f, "{x:?}"),
+            ArithExpr::Load(name) => write!(f, "{name}"),
+
+            ArithExpr::Neg(expr) => write!(f, "-{}", operand(expr)),
+            ArithExpr::Sub(lhs, rhs) => write!(f, "{} - {}", operand(lhs), operand(rhs)),
+            ArithExpr::Add(lhs, rhs) => write!(f, "{} + {}", operand(lhs), operand(rhs)),
+            ArithExpr::Div(lhs, rhs) => write!(f, "{} / {}", operand(lhs), operand(rhs)),
+            ArithExpr::Mul(lhs, rhs) => write!(f, "{} * {}", operand(lhs), operand(rhs)),
+        }
+    }
+}
+
 impl ArithExpr {
+    // The names of all the attributes the expression loads
+    pub fn attributes(&self) -> Vec<&str> {
+        match self {
+            ArithExpr::IntLit(_) | ArithExpr::FltLit(_) => Vec::new(),
+            ArithExpr::Load(name) => vec![name.as_str()],
+
+            ArithExpr::Neg(expr) => expr.attributes(),
+            ArithExpr::Sub(lhs, rhs)
+            | ArithExpr::Add(lhs, rhs)
+            | ArithExpr::Div(lhs, rhs)
+            | ArithExpr::Mul(lhs, rhs) => {
+                let mut atts = lhs.attributes();
+                atts.extend(rhs.attributes());
+                atts
+            }
+        }
+    }
+
     fn compile(
         &self,
         schema: &Schema,
@@ -209,7 +253,20 @@ impl Function {
         }
     }
 
+    pub fn output_type(&self) -> Type {
+        self.output_type
+    }
+
     pub fn eval(&self, record: &Record) -> MappedAttrData<'_> {
+        // any arithmetic on a null (from an outer join) is null
+        if self
+            .values
+            .iter()
+            .any(|v| matches!(v, Value::Load(att_idx) if record.is_null(*att_idx as usize)))
+        {
+            return MappedAttrData::Null;
+        }
+
         let mut values = self.values.iter().map(|v| unsafe {
             match v {
                 Value::IntLit(i) => AttrData { integer: *i },
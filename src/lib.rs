@@ -1,6 +1,13 @@
 pub const PAGE_SIZE: usize = 131072;
 pub const PIPE_BUFFER_SIZE: usize = 10000;
+// default memory budget in bytes for operators that spill to disk, like the sort in `OrderBy`
+pub const MEMORY_LIMIT: usize = 64 * PAGE_SIZE;
+// pages the buffer pool shared by all data files keeps in memory
+pub const BUFFER_POOL_FRAMES: usize = 256;
 
+mod aggregate;
+mod btree;
+mod buffer_pool;
 mod catalog;
 mod comparison;
 mod compiler;
@@ -11,6 +18,9 @@ mod relop;
 mod schema;
 mod types;
 
+pub use aggregate::*;
+pub use btree::*;
+pub use buffer_pool::*;
 pub use catalog::*;
 pub use comparison::*;
 pub use compiler::*;
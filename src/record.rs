@@ -10,6 +10,8 @@ enum AttrType {
     Integer,
     Float,
     String,
+    // the padding an outer join puts in place of the columns of an unmatched record
+    Null,
 }
 
 #[derive(Copy, Clone)]
@@ -34,6 +36,7 @@ pub enum MappedAttrData<'a> {
     Integer(i64),
     Float(f64),
     String(&'a str),
+    Null,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -41,6 +44,7 @@ pub enum ProjectedData {
     Integer(i64),
     Float(f64),
     String(String),
+    Null,
 }
 
 impl Into<ProjectedData> for MappedAttrData<'_> {
@@ -49,6 +53,7 @@ impl Into<ProjectedData> for MappedAttrData<'_> {
             MappedAttrData::Integer(val) => ProjectedData::Integer(val),
             MappedAttrData::Float(val) => ProjectedData::Float(val),
             MappedAttrData::String(val) => ProjectedData::String(val.to_string()),
+            MappedAttrData::Null => ProjectedData::Null,
         }
     }
 }
@@ -64,6 +69,7 @@ impl Hash for ProjectedData {
                 rounded.hash(state);
             }
             ProjectedData::String(val) => val.hash(state),
+            ProjectedData::Null => 0u8.hash(state),
         }
     }
 }
@@ -91,6 +97,7 @@ impl Hash for MappedAttrData<'_> {
             MappedAttrData::Integer(val) => val.hash(state),
             MappedAttrData::Float(val) => rounded_for_hash(*val).hash(state),
             MappedAttrData::String(val) => val.hash(state),
+            MappedAttrData::Null => 0u8.hash(state),
         }
     }
 }
@@ -122,6 +129,7 @@ impl From<Vec<MappedAttrData<'_>>> for Record {
                     record.strbuf.push_str(val);
                     record.strbuf.push('\0');
                 }
+                MappedAttrData::Null => record.push_null(),
             }
         }
         record
@@ -170,11 +178,21 @@ impl Record {
 
         for (_i, att) in atts.iter().enumerate() {
             attr_buf.clear();
-            buf_reader.read_until(b'|', &mut attr_buf).ok()?;
+            if buf_reader.read_until(b'|', &mut attr_buf).ok()? == 0 {
+                return None;
+            }
             if attr_buf.last() == Some(&b'|') {
                 attr_buf.pop();
             }
 
+            // an empty number is a null, which is how `to_bytes` writes them out. Empty strings
+            // stay empty strings.
+            if attr_buf.is_empty() && matches!(att.type_, Type::Integer | Type::Float) {
+                kinds.push(AttrType::Null);
+                data.push(AttrData { integer: 0 });
+                continue;
+            }
+
             match att.type_ {
                 Type::Integer => {
                     let s = String::from_utf8_lossy(&attr_buf);
@@ -232,6 +250,7 @@ impl Record {
                 let end = s.find('\0').unwrap_or(s.len());
                 Some(MappedAttrData::String(&s[..end]))
             }
+            AttrType::Null => Some(MappedAttrData::Null),
         }
     }
 
@@ -299,7 +318,7 @@ impl Record {
                 AttrType::Integer => {
                     self.data.push(other.data[i]);
                 }
-                AttrType::Float => {
+                AttrType::Float | AttrType::Null => {
                     self.data.push(other.data[i]);
                 }
                 AttrType::String => {
@@ -332,6 +351,15 @@ impl Record {
         self.data.push(AttrData { float: val });
     }
 
+    pub fn push_null(&mut self) {
+        self.kinds.push(AttrType::Null);
+        self.data.push(AttrData { integer: 0 });
+    }
+
+    pub fn is_null(&self, index: usize) -> bool {
+        self.kinds.get(index) == Some(&AttrType::Null)
+    }
+
     pub fn merge_left(&mut self, other: &Record) {
         let mut other = other.clone();
         other.merge_right(self);
@@ -347,6 +375,7 @@ impl Record {
                 MappedAttrData::Integer(val) => print!("{}: {} ", att_schema.name, val),
                 MappedAttrData::Float(val) => print!("{}: {} ", att_schema.name, val),
                 MappedAttrData::String(val) => print!("{}: {} ", att_schema.name, val),
+                MappedAttrData::Null => print!("{}: NULL ", att_schema.name),
             }
 
             if i < atts.len() - 1 {
@@ -357,6 +386,63 @@ impl Record {
         print!("}}");
     }
 
+    // The record the way pages store it. Every attribute is a tag with its type followed by 8
+    // bytes for numbers or a 4 byte length and the bytes for strings, nulls are just the tag.
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut bits = Vec::with_capacity(self.data.len() * 9);
+
+        for (i, data) in self.data.iter().enumerate() {
+            bits.push(self.kinds[i] as u8);
+
+            match self.kinds[i] {
+                AttrType::Integer => bits.extend_from_slice(&unsafe { data.integer }.to_le_bytes()),
+                AttrType::Float => bits.extend_from_slice(&unsafe { data.float }.to_le_bytes()),
+                AttrType::String => {
+                    let MappedAttrData::String(val) = self.get_column(i).unwrap() else {
+                        unreachable!()
+                    };
+                    bits.extend_from_slice(&(val.len() as u32).to_le_bytes());
+                    bits.extend_from_slice(val.as_bytes());
+                }
+                AttrType::Null => (),
+            }
+        }
+
+        bits
+    }
+
+    // Reads back a record written by `to_binary`, `bits` has to hold exactly one record
+    pub fn from_binary(&mut self, mut bits: &[u8]) -> Option<()> {
+        fn take<'a>(bits: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+            let (taken, rest) = bits.split_at_checked(len)?;
+            *bits = rest;
+            Some(taken)
+        }
+
+        let mut record = Record::new();
+        while let Some(&tag) = bits.first() {
+            bits = &bits[1..];
+
+            match tag {
+                tag if tag == AttrType::Integer as u8 => {
+                    record.push_int(i64::from_le_bytes(take(&mut bits, 8)?.try_into().ok()?))
+                }
+                tag if tag == AttrType::Float as u8 => {
+                    record.push_flt(f64::from_le_bytes(take(&mut bits, 8)?.try_into().ok()?))
+                }
+                tag if tag == AttrType::String as u8 => {
+                    let len = u32::from_le_bytes(take(&mut bits, 4)?.try_into().ok()?);
+                    record.push_str(std::str::from_utf8(take(&mut bits, len as usize)?).ok()?);
+                }
+                tag if tag == AttrType::Null as u8 => record.push_null(),
+                _ => return None,
+            }
+        }
+
+        *self = record;
+        Some(())
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = String::new();
 
@@ -379,6 +465,8 @@ impl Record {
                     let string_data = &s[..end];
                     buffer.push_str(string_data);
                 }
+                // nulls are written out as an empty field
+                AttrType::Null => (),
             }
             buffer.push('|');
         }
@@ -8,6 +8,12 @@ impl QueryExecutionTree {
     pub fn as_string(&self) -> String {
         self.root.as_string()
     }
+
+    // The error that ended the records early, if any. It has to be checked once they run out,
+    // the records until then may be incomplete otherwise.
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        self.root.take_error()
+    }
 }
 
 impl Iterator for QueryExecutionTree {
@@ -18,6 +24,8 @@ impl Iterator for QueryExecutionTree {
     }
 }
 
+// there's only a handful of operators per query, so the size of the biggest one doesn't matter
+#[allow(clippy::large_enum_variant)]
 pub enum RelOp {
     Scan(Scan),
     EmptyTableScan,
@@ -30,7 +38,9 @@ pub enum RelOp {
     ApplyFunction(ApplyFunction),
     GroupBy(GroupBy),
     OrderBy(OrderBy),
+    Limit(Limit),
     WriteOut(WriteOut),
+    Instrument(Instrument),
 }
 
 impl RelOp {
@@ -84,8 +94,94 @@ impl RelOp {
                 format_with_producers!("ApplyFunction", apply_function.producer)
             }
             RelOp::GroupBy(group_by) => format_with_producers!("GroupBy", group_by.producer),
-            RelOp::OrderBy(order_by) => format_with_producers!("OrderBy", order_by.producer),
+            RelOp::OrderBy(order_by) => match order_by.limit {
+                Some(limit) => {
+                    format_with_producers!(format!("OrderBy(top {limit})"), order_by.producer)
+                }
+                None => format_with_producers!("OrderBy", order_by.producer),
+            },
+            RelOp::Limit(limit) => format_with_producers!(
+                format!("Limit({}, offset {})", limit.limit, limit.offset),
+                limit.producer
+            ),
             RelOp::WriteOut(write_out) => format_with_producers!("WriteOut", write_out.producer),
+            // the numbers go on the line of the operator that's instrumented
+            RelOp::Instrument(instrument) => {
+                let producer = instrument.producer.as_string();
+                match producer.split_once('\n') {
+                    Some((first, rest)) => format!("{first} {}\n{rest}", instrument.annotation()),
+                    None => format!("{producer} {}", instrument.annotation()),
+                }
+            }
+        }
+    }
+
+    // The operators this one reads its records from
+    pub fn producers(&self) -> Vec<&RelOp> {
+        match self {
+            RelOp::Scan(_) | RelOp::EmptyTableScan => Vec::new(),
+            RelOp::Select(select) => vec![&select.producer],
+            RelOp::Project(project) => vec![&project.producer],
+            RelOp::NestedLoopJoin(join) => vec![&join.left_producer, &join.right_producer],
+            RelOp::MergeJoin(join) => vec![&join.left_producer, &join.right_producer],
+            RelOp::HashJoin(join) => vec![&join.left_producer, &join.right_producer],
+            RelOp::DupElim(dup_elim) => vec![&dup_elim.producer],
+            RelOp::ApplyFunction(apply_function) => vec![&apply_function.producer],
+            RelOp::GroupBy(group_by) => vec![&group_by.producer],
+            RelOp::OrderBy(order_by) => vec![&order_by.producer],
+            RelOp::Limit(limit) => vec![&limit.producer],
+            RelOp::WriteOut(write_out) => vec![&write_out.producer],
+            RelOp::Instrument(instrument) => vec![&instrument.producer],
+        }
+    }
+
+    fn producers_mut(&mut self) -> Vec<&mut RelOp> {
+        match self {
+            RelOp::Scan(_) | RelOp::EmptyTableScan => Vec::new(),
+            RelOp::Select(select) => vec![&mut select.producer],
+            RelOp::Project(project) => vec![&mut project.producer],
+            RelOp::NestedLoopJoin(join) => vec![&mut join.left_producer, &mut join.right_producer],
+            RelOp::MergeJoin(join) => vec![&mut join.left_producer, &mut join.right_producer],
+            RelOp::HashJoin(join) => vec![&mut join.left_producer, &mut join.right_producer],
+            RelOp::DupElim(dup_elim) => vec![&mut dup_elim.producer],
+            RelOp::ApplyFunction(apply_function) => vec![&mut apply_function.producer],
+            RelOp::GroupBy(group_by) => vec![&mut group_by.producer],
+            RelOp::OrderBy(order_by) => vec![&mut order_by.producer],
+            RelOp::Limit(limit) => vec![&mut limit.producer],
+            RelOp::WriteOut(write_out) => vec![&mut write_out.producer],
+            RelOp::Instrument(instrument) => vec![&mut instrument.producer],
+        }
+    }
+
+    // Operators that fail midway keep the error and stop producing records, as if their input
+    // ended. This gives the error of this operator or of the first one below it that failed.
+    pub fn take_error(&mut self) -> Option<anyhow::Error> {
+        let error = match self {
+            RelOp::GroupBy(group_by) => group_by.error.take(),
+            RelOp::HashJoin(hash_join) => hash_join.error.take(),
+            RelOp::OrderBy(order_by) => order_by.error.take(),
+            _ => None,
+        };
+
+        error.or_else(|| self.producers_mut().into_iter().find_map(RelOp::take_error))
+    }
+
+    // Bytes of records the operator holds in memory right now, not counting its producers
+    pub fn memory_usage(&self) -> usize {
+        let size = |records: &mut dyn Iterator<Item = &Record>| {
+            records.map(Record::get_size).sum::<usize>()
+        };
+
+        match self {
+            RelOp::NestedLoopJoin(join) => size(&mut join.records.iter()),
+            RelOp::MergeJoin(join) => size(&mut join.buf.iter()),
+            RelOp::HashJoin(join) => {
+                size(&mut join.hash_table.values().flatten().map(|(record, _)| record))
+                    + size(&mut join.buf.iter())
+            }
+            RelOp::DupElim(dup_elim) => size(&mut dup_elim.seen.iter()),
+            RelOp::OrderBy(order_by) => size(&mut order_by.records.iter()),
+            _ => 0,
         }
     }
 }
@@ -114,7 +210,9 @@ impl Iterator for RelOp {
             ApplyFunction,
             GroupBy,
             OrderBy,
-            WriteOut
+            Limit,
+            WriteOut,
+            Instrument
         )
     }
 }
@@ -169,8 +267,32 @@ impl Project {
     }
 }
 
+// Builds the output record of an outer join for a left record that found no match
+fn pad_right(left: &Record, right_atts: usize) -> Record {
+    let mut joined = left.clone();
+    for _ in 0..right_atts {
+        joined.push_null();
+    }
+    joined
+}
+
+// Builds the output record of an outer join for a right record that found no match
+fn pad_left(left_atts: usize, right: &Record) -> Record {
+    let mut joined = Record::new();
+    for _ in 0..left_atts {
+        joined.push_null();
+    }
+    joined.merge_right(right);
+    joined
+}
+
 pub struct NestedLoopJoin {
     pub predicate: Cnf,
+    pub join_type: JoinType,
+
+    // number of attributes on each side, needed to pad unmatched records in outer joins
+    pub left_atts: usize,
+    pub right_atts: usize,
 
     pub records: Vec<Record>,
 
@@ -182,40 +304,58 @@ impl NestedLoopJoin {
     fn next(&mut self) -> Option<Record> {
         if self.records.is_empty() {
             let right_collection: Vec<Record> = self.right_producer.by_ref().collect();
-            self.records = self
-                .left_producer
-                .by_ref()
-                .flat_map(|left_record| {
-                    right_collection
-                        .iter()
-                        .filter_map(|right_record| {
-                            if self.predicate.run(&left_record, right_record) {
-                                let mut joined = left_record.clone();
-                                joined.merge_right(right_record);
-
-                                Some(joined)
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect();
+            let mut right_matched = vec![false; right_collection.len()];
+
+            for left_record in self.left_producer.by_ref() {
+                let mut left_matched = false;
+
+                for (right_record, right_matched) in
+                    right_collection.iter().zip(right_matched.iter_mut())
+                {
+                    if self.predicate.run(&left_record, right_record) {
+                        let mut joined = left_record.clone();
+                        joined.merge_right(right_record);
+                        self.records.push(joined);
+
+                        left_matched = true;
+                        *right_matched = true;
+                    }
+                }
+
+                if !left_matched && self.join_type.preserves_left() {
+                    self.records.push(pad_right(&left_record, self.right_atts));
+                }
+            }
+
+            if self.join_type.preserves_right() {
+                let unmatched = right_collection
+                    .iter()
+                    .zip(right_matched)
+                    .filter(|(_, matched)| !matched)
+                    .map(|(right_record, _)| pad_left(self.left_atts, right_record));
+                self.records.extend(unmatched);
+            }
         }
 
         self.records.pop()
     }
 }
 
-// Assumes input is already sorted, make sure to combine with a `GroupBy` if not
+// Assumes input is already sorted, make sure to combine with an `OrderBy` if not
 pub struct MergeJoin {
     pub buf: Vec<Record>,
 
     pub predicate: Cnf,
+    pub join_type: JoinType,
+
+    pub left_atts: usize,
+    pub right_atts: usize,
 
     pub left_ordering: OrderMaker,
     pub right_ordering: OrderMaker,
 
+    // the next record of each input that hasn't been joined yet, `None` once it is exhausted
+    pub started: bool,
     pub left_record: Option<Record>,
     pub right_record: Option<Record>,
 
@@ -225,91 +365,119 @@ pub struct MergeJoin {
 
 impl MergeJoin {
     fn next(&mut self) -> Option<Record> {
-        if !self.buf.is_empty() {
-            return self.buf.pop();
-        }
+        use std::cmp::Ordering;
 
-        if self.left_record.is_none() {
-            self.left_record = Some(self.left_producer.next()?);
-        }
-
-        if self.right_record.is_none() {
-            self.right_record = Some(self.right_producer.next()?);
-        }
+        loop {
+            if let Some(record) = self.buf.pop() {
+                return Some(record);
+            }
 
-        match self.ordering(
-            self.left_record.as_ref().unwrap(),
-            self.right_record.as_ref().unwrap(),
-        ) {
-            std::cmp::Ordering::Less => {
-                self.left_record = Some(self.left_producer.next()?);
-                return self.next();
+            if !self.started {
+                self.left_record = self.left_producer.next();
+                self.right_record = self.right_producer.next();
+                self.started = true;
             }
-            std::cmp::Ordering::Greater => {
-                self.right_record = Some(self.right_producer.next()?);
-                return self.next();
+
+            let ordering = match (&self.left_record, &self.right_record) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(left), Some(right)) => self.ordering(left, right),
+            };
+
+            match ordering {
+                Ordering::Less => {
+                    let left = self.left_record.take().unwrap();
+                    self.left_record = self.left_producer.next();
+
+                    if self.join_type.preserves_left() {
+                        return Some(pad_right(&left, self.right_atts));
+                    }
+                }
+                Ordering::Greater => {
+                    let right = self.right_record.take().unwrap();
+                    self.right_record = self.right_producer.next();
+
+                    if self.join_type.preserves_right() {
+                        return Some(pad_left(self.left_atts, &right));
+                    }
+                }
+                Ordering::Equal => {
+                    let left_group = Self::next_group(
+                        &mut self.left_record,
+                        &mut self.left_producer,
+                        &self.left_ordering,
+                    );
+                    let right_group = Self::next_group(
+                        &mut self.right_record,
+                        &mut self.right_producer,
+                        &self.right_ordering,
+                    );
+
+                    self.join_groups(&left_group, &right_group);
+                }
             }
-            _ => (),
         }
+    }
 
-        fn cartesian_product(
-            predicate: &Cnf,
-            left_records: &[Record],
-            right_records: &[Record],
-        ) -> Vec<Record> {
-            left_records
-                .iter()
-                .flat_map(|left_record| {
-                    right_records
-                        .iter()
-                        .filter(|right_record| predicate.run(left_record, right_record))
-                        .map(|right_record| {
-                            let mut joined = left_record.clone();
-                            joined.merge_right(right_record);
-                            joined
-                        })
-                        .collect::<Vec<_>>()
-                })
-                .collect()
-        }
-
-        let mut right_records = Vec::new();
-        let mut next_right_record = self.right_record.take();
-        while self.ordering(
-            self.left_record.as_ref().unwrap(),
-            next_right_record.as_ref().unwrap(),
-        ) == std::cmp::Ordering::Equal
-        {
-            right_records.push(next_right_record.take().unwrap());
-            next_right_record = self.right_producer.next();
-
-            if next_right_record.is_none() {
+    // Pulls the run of records equal to `lookahead` out of the producer, leaving the first
+    // record of the next run in `lookahead`
+    fn next_group(
+        lookahead: &mut Option<Record>,
+        producer: &mut RelOp,
+        ordering: &OrderMaker,
+    ) -> Vec<Record> {
+        let mut group = vec![lookahead.take().unwrap()];
+
+        *lookahead = producer.next();
+        while let Some(record) = lookahead.take() {
+            if ordering.run(&group[0], &record) != std::cmp::Ordering::Equal {
+                *lookahead = Some(record);
                 break;
             }
+
+            group.push(record);
+            *lookahead = producer.next();
         }
 
-        let mut left_records = Vec::new();
-        let mut next_left_record = self.left_record.take();
-        while self.ordering(
-            next_left_record.as_ref().unwrap(),
-            self.right_record.as_ref().unwrap(),
-        ) == std::cmp::Ordering::Equal
-        {
-            left_records.push(next_left_record.take().unwrap());
-            next_left_record = self.left_producer.next();
+        group
+    }
 
-            if next_left_record.is_none() {
-                break;
+    fn join_groups(&mut self, left_records: &[Record], right_records: &[Record]) {
+        let mut right_matched = vec![false; right_records.len()];
+
+        for left_record in left_records {
+            let mut left_matched = false;
+
+            for (right_record, right_matched) in right_records.iter().zip(right_matched.iter_mut())
+            {
+                if self.predicate.run(left_record, right_record) {
+                    let mut joined = left_record.clone();
+                    joined.merge_right(right_record);
+                    self.buf.push(joined);
+
+                    left_matched = true;
+                    *right_matched = true;
+                }
             }
-        }
 
-        self.left_record = next_left_record;
-        self.right_record = next_right_record;
+            if !left_matched && self.join_type.preserves_left() {
+                self.buf.push(pad_right(left_record, self.right_atts));
+            }
+        }
 
-        let cartesian_product = cartesian_product(&self.predicate, &left_records, &right_records);
-        self.buf.extend(cartesian_product);
+        if self.join_type.preserves_right() {
+            for (right_record, _) in right_records
+                .iter()
+                .zip(right_matched)
+                .filter(|(_, matched)| !matched)
+            {
+                self.buf.push(pad_left(self.left_atts, right_record));
+            }
+        }
 
-        self.next()
+        // records are popped off the end, so this keeps the output in sorted order
+        self.buf.reverse();
     }
 
     fn ordering(&self, left: &Record, right: &Record) -> std::cmp::Ordering {
@@ -318,95 +486,522 @@ impl MergeJoin {
     }
 }
 
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap};
+
+// A temporary file that operators spill records to when they run out of memory. Records are
+// appended until `finish`, after which they can be read back in the same order. The file is
+// deleted once dropped.
+pub struct SpillFile {
+    file: DBFile,
+    path: std::path::PathBuf,
+}
+
+impl SpillFile {
+    fn create(schema: &Schema) -> anyhow::Result<Self> {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+        let path = std::env::temp_dir().join(format!(
+            "spill-{}-{}.bin",
+            std::process::id(),
+            NEXT_FILE.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut file = DBFile::new();
+        file.set_schema(schema.clone());
+        file.create(&path, FileType::Heap)?;
+
+        Ok(SpillFile { file, path })
+    }
+
+    fn append(&mut self, record: Record) -> anyhow::Result<()> {
+        self.file.append_record(record)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<()> {
+        self.file.close()?;
+        self.file.open(&self.path)
+    }
+
+    // Starts reading the records from the first one again
+    fn rewind(&mut self) {
+        self.file.move_first();
+    }
+
+    fn next(&mut self) -> anyhow::Result<Option<Record>> {
+        let mut record = Record::new();
+        if self.file.get_next(&mut record)? {
+            Ok(Some(record))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
 
 pub struct HashJoin {
     pub predicate: Cnf,
+    pub join_type: JoinType,
     pub fill_left: bool,
 
-    pub hash_table: HashMap<Vec<ProjectedData>, Vec<Record>>,
+    pub left_atts: usize,
+    pub right_atts: usize,
+
+    // every build record is stored along with whether it found a match, so outer joins can emit
+    // the unmatched ones once the probe side is exhausted
+    pub hash_table: HashMap<Vec<ProjectedData>, Vec<(Record, bool)>>,
+    pub filled: bool,
 
     pub buf: Vec<Record>,
 
     pub left_projection: Vec<i32>,
     pub right_projection: Vec<i32>,
 
+    // Once the build side outgrows `memory_limit` bytes, both inputs are hashed into partitions
+    // on disk and the join is done one pair of (build, probe) partitions at a time. The schemas
+    // are needed to write the partitions out.
+    pub memory_limit: usize,
+    pub left_schema: Schema,
+    pub right_schema: Schema,
+    pub partitions: Vec<Partition>,
+    pub probe_partition: Option<SpillFile>,
+    // set while a partition that can't be split up any further is joined a block at a time
+    pub block_join: Option<BlockJoin>,
+
+    // set when writing or reading back a partition failed, which ends the output
+    pub error: Option<anyhow::Error>,
+
     pub left_producer: Box<RelOp>,
     pub right_producer: Box<RelOp>,
 }
 
+// How many times a partition that's over the memory limit gets partitioned again before it's
+// joined a block at a time instead
+const MAX_PARTITION_DEPTH: u32 = 8;
+
+// A build partition and the probe partition with the records that can match it
+pub struct Partition {
+    build: SpillFile,
+    probe: SpillFile,
+    // bytes of records in the build partition
+    build_size: usize,
+    // how many times the records were partitioned to get here, which seeds the hash of the next
+    // round
+    depth: u32,
+}
+
+impl Partition {
+    fn pair(
+        build: Vec<(SpillFile, usize)>,
+        probe: Vec<(SpillFile, usize)>,
+        depth: u32,
+    ) -> Vec<Self> {
+        build
+            .into_iter()
+            .zip(probe)
+            .filter(|((_, build_size), (_, probe_size))| *build_size > 0 || *probe_size > 0)
+            .map(|((build, build_size), (probe, _))| Partition {
+                build,
+                probe,
+                build_size,
+                depth,
+            })
+            .collect()
+    }
+}
+
+// A build partition joined a block of `memory_limit` bytes at a time, the probe partition gets
+// read again for every block
+pub struct BlockJoin {
+    build: SpillFile,
+    // whether the block in the hash table is the last one
+    last_block: bool,
+    // which records of the probe partition found a match in any block so far, they can only be
+    // padded for outer joins once the last block didn't match them either
+    probe_matched: Vec<bool>,
+    probe_pos: usize,
+}
+
+// Hashes `records` on `projection` into `num_partitions` spill files, along with how many bytes
+// of records went into each of them. The hash is seeded with `depth`, so records that ended up
+// in the same partition get spread out when it's partitioned again.
+fn write_partitions(
+    records: impl Iterator<Item = anyhow::Result<Record>>,
+    schema: &Schema,
+    projection: &[i32],
+    depth: u32,
+    num_partitions: usize,
+) -> anyhow::Result<Vec<(SpillFile, usize)>> {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    let mut partitions = Vec::with_capacity(num_partitions);
+    for _ in 0..num_partitions {
+        partitions.push((SpillFile::create(schema)?, 0));
+    }
+
+    for record in records {
+        let record = record?;
+
+        let mut hasher = DefaultHasher::new();
+        depth.hash(&mut hasher);
+        record.get_projected_data(projection).hash(&mut hasher);
+
+        let (partition, size) = &mut partitions[hasher.finish() as usize % num_partitions];
+        *size += record.get_size();
+        partition.append(record)?;
+    }
+
+    for (partition, _) in &mut partitions {
+        partition.finish()?;
+    }
+
+    Ok(partitions)
+}
+
 impl HashJoin {
-    fn fill_hash_table(&mut self) {
+    fn build_projection(&self) -> &[i32] {
         if self.fill_left {
-            for left_record in self.left_producer.by_ref() {
-                let projected_data = left_record.get_projected_data(&self.left_projection);
-                if let Some(records) = self.hash_table.get_mut(&projected_data) {
-                    records.push(left_record);
+            &self.left_projection
+        } else {
+            &self.right_projection
+        }
+    }
+
+    fn probe_projection(&self) -> &[i32] {
+        if self.fill_left {
+            &self.right_projection
+        } else {
+            &self.left_projection
+        }
+    }
+
+    fn build_schema(&self) -> &Schema {
+        if self.fill_left {
+            &self.left_schema
+        } else {
+            &self.right_schema
+        }
+    }
+
+    fn probe_schema(&self) -> &Schema {
+        if self.fill_left {
+            &self.right_schema
+        } else {
+            &self.left_schema
+        }
+    }
+
+    // Every partition buffers a page of its build and a page of its probe file while they're
+    // written, so that's what bounds the fan-out
+    fn num_partitions(&self) -> usize {
+        (self.memory_limit / (2 * PAGE_SIZE)).max(2)
+    }
+
+    fn add_build_record(&mut self, record: Record) {
+        let projected_data = record.get_projected_data(self.build_projection());
+        self.hash_table
+            .entry(projected_data)
+            .or_default()
+            .push((record, false));
+    }
+
+    fn fill_hash_table(&mut self) -> anyhow::Result<()> {
+        self.filled = true;
+
+        let mut used = 0;
+        loop {
+            let record = if self.fill_left {
+                self.left_producer.next()
+            } else {
+                self.right_producer.next()
+            };
+            let Some(record) = record else { break };
+
+            used += record.get_size();
+            self.add_build_record(record);
+
+            if used > self.memory_limit {
+                self.partition_inputs()?;
+                self.load_next_partition()?;
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Hashes what's in the hash table so far, the rest of the build side and the whole probe side
+    // into partitions on disk, such that matching records end up in partitions with the same index
+    fn partition_inputs(&mut self) -> anyhow::Result<()> {
+        let num_partitions = self.num_partitions();
+
+        let buffered = std::mem::take(&mut self.hash_table)
+            .into_values()
+            .flatten()
+            .map(|(record, _)| Ok(record));
+        let (build_producer, probe_producer) = if self.fill_left {
+            (&mut self.left_producer, &mut self.right_producer)
+        } else {
+            (&mut self.right_producer, &mut self.left_producer)
+        };
+        let (build_schema, probe_schema) = if self.fill_left {
+            (&self.left_schema, &self.right_schema)
+        } else {
+            (&self.right_schema, &self.left_schema)
+        };
+        let (build_projection, probe_projection) = if self.fill_left {
+            (&self.left_projection, &self.right_projection)
+        } else {
+            (&self.right_projection, &self.left_projection)
+        };
+
+        let build = write_partitions(
+            buffered.chain(build_producer.by_ref().map(Ok)),
+            build_schema,
+            build_projection,
+            0,
+            num_partitions,
+        )?;
+        let probe = write_partitions(
+            probe_producer.by_ref().map(Ok),
+            probe_schema,
+            probe_projection,
+            0,
+            num_partitions,
+        )?;
+        self.partitions.extend(Partition::pair(build, probe, 0));
+
+        Ok(())
+    }
+
+    // Splits a partition that's over the memory limit up into smaller ones with another hash seed
+    fn repartition(&self, partition: Partition) -> anyhow::Result<Vec<Partition>> {
+        let Partition {
+            mut build,
+            mut probe,
+            depth,
+            ..
+        } = partition;
+
+        let build = write_partitions(
+            std::iter::from_fn(|| build.next().transpose()),
+            self.build_schema(),
+            self.build_projection(),
+            depth + 1,
+            self.num_partitions(),
+        )?;
+        let probe = write_partitions(
+            std::iter::from_fn(|| probe.next().transpose()),
+            self.probe_schema(),
+            self.probe_projection(),
+            depth + 1,
+            self.num_partitions(),
+        )?;
+
+        Ok(Partition::pair(build, probe, depth + 1))
+    }
+
+    // Builds the hash table from the next build partition and makes its probe partition the probe
+    // side. A partition that's over the memory limit gets partitioned again, and once that
+    // doesn't split it up anymore it's joined a block at a time.
+    fn load_next_partition(&mut self) -> anyhow::Result<bool> {
+        loop {
+            let Some(mut partition) = self.partitions.pop() else {
+                self.probe_partition = None;
+                return Ok(false);
+            };
+
+            if partition.build_size <= self.memory_limit {
+                while let Some(record) = partition.build.next()? {
+                    self.add_build_record(record);
+                }
+                self.probe_partition = Some(partition.probe);
+
+                return Ok(true);
+            }
+
+            if partition.depth < MAX_PARTITION_DEPTH {
+                let build_size = partition.build_size;
+                for mut split in self.repartition(partition)? {
+                    // nothing got split off, so the build records most likely all have the same
+                    // key and partitioning it again won't help either
+                    if split.build_size == build_size {
+                        split.depth = MAX_PARTITION_DEPTH;
+                    }
+                    self.partitions.push(split);
+                }
+
+                continue;
+            }
+
+            self.block_join = Some(BlockJoin {
+                build: partition.build,
+                last_block: false,
+                probe_matched: Vec::new(),
+                probe_pos: 0,
+            });
+            self.load_block()?;
+            self.probe_partition = Some(partition.probe);
+
+            return Ok(true);
+        }
+    }
+
+    // Fills the hash table with the next block of the partition that's joined a block at a time
+    fn load_block(&mut self) -> anyhow::Result<()> {
+        let Some(block_join) = &mut self.block_join else {
+            return Ok(());
+        };
+
+        let mut records = Vec::new();
+        let mut used = 0;
+        while used <= self.memory_limit {
+            let Some(record) = block_join.build.next()? else {
+                block_join.last_block = true;
+                break;
+            };
+
+            used += record.get_size();
+            records.push(record);
+        }
+        block_join.probe_pos = 0;
+
+        for record in records {
+            self.add_build_record(record);
+        }
+
+        Ok(())
+    }
+
+    // Moves on to the next block once the probe partition was read for the one before, gives
+    // false if there's no partition joined a block at a time or it was the last block
+    fn next_block(&mut self) -> anyhow::Result<bool> {
+        let more_blocks = matches!(&self.block_join, Some(block_join) if !block_join.last_block);
+        if !more_blocks {
+            self.block_join = None;
+            return Ok(false);
+        }
+
+        self.load_block()?;
+        if let Some(probe) = &mut self.probe_partition {
+            probe.rewind();
+        }
+
+        Ok(true)
+    }
+
+    fn probe(&mut self, record: Record) {
+        let projected_data = record.get_projected_data(self.probe_projection());
+
+        let mut matched = false;
+        if let Some(records) = self.hash_table.get_mut(&projected_data) {
+            for (build_record, build_matched) in records.iter_mut() {
+                let (left_record, right_record) = if self.fill_left {
+                    (&*build_record, &record)
                 } else {
-                    self.hash_table.insert(projected_data, vec![left_record]);
+                    (&record, &*build_record)
+                };
+
+                if self.predicate.run(left_record, right_record) {
+                    let mut joined = left_record.clone();
+                    joined.merge_right(right_record);
+                    self.buf.push(joined);
+
+                    matched = true;
+                    *build_matched = true;
                 }
             }
+        }
+
+        // when joining a block at a time, a probe record is only unmatched if no block matched it
+        if let Some(block_join) = &mut self.block_join {
+            let pos = block_join.probe_pos;
+            block_join.probe_pos += 1;
+            if pos == block_join.probe_matched.len() {
+                block_join.probe_matched.push(false);
+            }
+            block_join.probe_matched[pos] |= matched;
+
+            matched = block_join.probe_matched[pos] || !block_join.last_block;
+        }
+
+        if !matched {
+            if self.fill_left && self.join_type.preserves_right() {
+                self.buf.push(pad_left(self.left_atts, &record));
+            } else if !self.fill_left && self.join_type.preserves_left() {
+                self.buf.push(pad_right(&record, self.right_atts));
+            }
+        }
+    }
+
+    // Once the probe side is exhausted, emits the build records that never found a match if the
+    // join type preserves the build side
+    fn emit_unmatched(&mut self) {
+        let preserves_build = if self.fill_left {
+            self.join_type.preserves_left()
         } else {
-            for right_record in self.right_producer.by_ref() {
-                let projected_data = right_record.get_projected_data(&self.right_projection);
-                if let Some(records) = self.hash_table.get_mut(&projected_data) {
-                    records.push(right_record);
+            self.join_type.preserves_right()
+        };
+
+        for (record, matched) in std::mem::take(&mut self.hash_table).into_values().flatten() {
+            if !matched && preserves_build {
+                if self.fill_left {
+                    self.buf.push(pad_right(&record, self.right_atts));
                 } else {
-                    self.hash_table.insert(projected_data, vec![right_record]);
+                    self.buf.push(pad_left(self.left_atts, &record));
                 }
             }
         }
     }
 
     pub fn next(&mut self) -> Option<Record> {
-        if !self.buf.is_empty() {
-            return self.buf.pop();
-        }
-        if self.hash_table.len() == 0 {
-            self.fill_hash_table();
+        if self.error.is_some() {
+            return None;
         }
 
-        if self.fill_left {
-            loop {
-                let record = self.right_producer.next()?;
-                let projected_data = record.get_projected_data(&self.right_projection);
+        match self.next_record() {
+            Ok(record) => record,
+            Err(error) => {
+                self.error = Some(error);
+                self.buf.clear();
+                None
+            }
+        }
+    }
 
-                let Some(records) = self.hash_table.get(&projected_data) else {
-                    continue;
-                };
-                let records: Vec<Record> = records
-                    .iter()
-                    .filter(|left_record: &&Record| self.predicate.run(*left_record, &record))
-                    .map(|left_record: &Record| {
-                        let mut joined = left_record.clone();
-                        joined.merge_right(&record);
-                        joined
-                    })
-                    .collect();
+    fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        loop {
+            if let Some(record) = self.buf.pop() {
+                return Ok(Some(record));
+            }
 
-                self.buf.extend(records);
-                return self.next();
+            if !self.filled {
+                self.fill_hash_table()?;
             }
-        } else {
-            loop {
-                let record = self.left_producer.next()?;
-                let projected_data = record.get_projected_data(&self.left_projection);
 
-                let Some(records) = self.hash_table.get(&projected_data) else {
-                    continue;
-                };
-                let records: Vec<Record> = records
-                    .iter()
-                    .filter(|right_record: &&Record| self.predicate.run(&record, *right_record))
-                    .map(|right_record: &Record| {
-                        let mut joined = record.clone();
-                        joined.merge_right(&right_record);
-                        joined
-                    })
-                    .collect();
+            let probe_record = if let Some(partition) = &mut self.probe_partition {
+                partition.next()?
+            } else if self.fill_left {
+                self.right_producer.next()
+            } else {
+                self.left_producer.next()
+            };
 
-                self.buf.extend(records);
-                return self.next();
+            match probe_record {
+                Some(record) => self.probe(record),
+                None => {
+                    self.emit_unmatched();
+                    if !self.next_block()? && !self.load_next_partition()? {
+                        return Ok(self.buf.pop());
+                    }
+                }
             }
         }
     }
@@ -445,35 +1040,86 @@ impl ApplyFunction {
     }
 }
 
+// Assumes input is sorted on the grouping attributes. Emits one record per group holding the
+// grouping attributes followed by the aggregates.
 pub struct GroupBy {
     pub grouping: OrderMaker,
+    pub aggregates: Vec<AggregateFunction>,
 
-    pub current_group: Vec<Record>,
     pub next_record: Option<Record>,
+    pub done: bool,
+    // set when an aggregate fails, no more groups get produced then
+    pub error: Option<anyhow::Error>,
 
     pub producer: Box<RelOp>,
 }
 
 impl GroupBy {
     fn next(&mut self) -> Option<Record> {
-        if self.current_group.is_empty() {
-            if let Some(next_record) = self.next_record.take() {
-                self.current_group.push(next_record);
-            } else {
-                self.current_group.push(self.producer.next()?);
+        if self.done {
+            return None;
+        }
+
+        match self.next_group() {
+            Ok(record) => record,
+            Err(error) => {
+                self.error = Some(error);
+                self.done = true;
+                None
             }
+        }
+    }
 
-            while let Some(record) = self.producer.next() {
-                if self.grouping.run(&self.current_group[0], &record) == std::cmp::Ordering::Equal {
-                    self.current_group.push(record);
-                } else {
-                    self.next_record = Some(record);
-                    break;
-                }
+    fn next_group(&mut self) -> anyhow::Result<Option<Record>> {
+        self.aggregates
+            .iter_mut()
+            .for_each(AggregateFunction::reset);
+
+        let Some(first) = self.next_record.take().or_else(|| self.producer.next()) else {
+            self.done = true;
+
+            // without grouping attributes all records form a single group, which still has to
+            // produce its aggregates when the input is empty
+            return Ok(self.grouping.atts.is_empty().then(|| self.output(None)));
+        };
+
+        for aggregate in &mut self.aggregates {
+            aggregate.accumulate(&first)?;
+        }
+
+        for record in self.producer.by_ref() {
+            if self.grouping.run(&first, &record) != std::cmp::Ordering::Equal {
+                self.next_record = Some(record);
+                break;
+            }
+
+            for aggregate in &mut self.aggregates {
+                aggregate.accumulate(&record)?;
             }
         }
 
-        todo!()
+        if self.next_record.is_none() {
+            self.done = true;
+        }
+
+        Ok(Some(self.output(Some(first))))
+    }
+
+    fn output(&self, first: Option<Record>) -> Record {
+        let mut record = match first {
+            Some(mut record) => {
+                let grouping_atts = self.grouping.atts.iter().map(|(att, _)| *att);
+                record.project(&grouping_atts.collect::<Vec<_>>());
+                record
+            }
+            None => Record::new(),
+        };
+
+        for aggregate in &self.aggregates {
+            aggregate.push_result(&mut record);
+        }
+
+        record
     }
 }
 
@@ -482,21 +1128,326 @@ pub struct OrderBy {
     pub records: Vec<Record>,
     pub producer: Box<RelOp>,
     pub ascending: bool,
+
+    // only the first `limit` records of the sorted output are needed, set when there is a
+    // `Limit` right above
+    pub limit: Option<usize>,
+
+    // schema of the records, needed to read spilled runs back in
+    pub schema: Schema,
+    // how many bytes of records get sorted in memory before they're spilled to disk as a run
+    pub memory_limit: usize,
+    // the spilled runs, keyed on their heads so the one with the next record is on top
+    pub runs: BinaryHeap<SortedRun>,
+
+    // set when spilling or reading back a run failed, which ends the output
+    pub error: Option<anyhow::Error>,
+}
+
+// Runs merged at once, each open run holds on to a file and a page of it. More runs than that
+// get merged into longer runs first.
+const MAX_MERGE_FAN_IN: usize = 64;
+
+// A run of records sorted in output order and spilled to disk by `OrderBy`
+pub struct SortedRun {
+    file: SpillFile,
+    head: Option<Record>,
+
+    ordering: OrderMaker,
+    ascending: bool,
+}
+
+impl SortedRun {
+    fn spill(
+        records: impl Iterator<Item = Record>,
+        schema: &Schema,
+        ordering: &OrderMaker,
+        ascending: bool,
+    ) -> anyhow::Result<Self> {
+        let mut file = SpillFile::create(schema)?;
+        for record in records {
+            file.append(record)?;
+        }
+        file.finish()?;
+
+        let head = file.next()?;
+        Ok(SortedRun {
+            file,
+            head,
+            ordering: ordering.clone(),
+            ascending,
+        })
+    }
+
+    // Merges `runs` into a single longer run
+    fn merge(mut runs: BinaryHeap<SortedRun>, schema: &Schema) -> anyhow::Result<Self> {
+        let Some(run) = runs.peek() else {
+            anyhow::bail!("no runs to merge");
+        };
+        let (ordering, ascending) = (run.ordering.clone(), run.ascending);
+
+        let mut file = SpillFile::create(schema)?;
+        while let Some(record) = pop_first(&mut runs)? {
+            file.append(record)?;
+        }
+        file.finish()?;
+
+        let head = file.next()?;
+        Ok(SortedRun {
+            file,
+            head,
+            ordering,
+            ascending,
+        })
+    }
+
+    // Returns the smallest record left in the run and reads in the one after it
+    fn pop(&mut self) -> anyhow::Result<Option<Record>> {
+        let next = self.file.next()?;
+        Ok(std::mem::replace(&mut self.head, next))
+    }
+}
+
+// `BinaryHeap` is a max-heap, so the run whose head comes first in the output is the greatest.
+// Runs that are used up come last.
+impl Ord for SortedRun {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match (&self.head, &other.head) {
+            (Some(head), Some(other_head)) if self.ascending => self.ordering.run(other_head, head),
+            (Some(head), Some(other_head)) => self.ordering.run(head, other_head),
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (None, None) => std::cmp::Ordering::Equal,
+        }
+    }
+}
+
+impl PartialOrd for SortedRun {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
+impl PartialEq for SortedRun {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for SortedRun {}
+
+// Takes the next record in the output off the run on top of the heap
+fn pop_first(runs: &mut BinaryHeap<SortedRun>) -> anyhow::Result<Option<Record>> {
+    let Some(mut first) = runs.peek_mut() else {
+        return Ok(None);
+    };
+
+    let record = first.pop()?;
+    // the run moves down the heap once `first` is dropped, or leaves it when it's used up
+    if first.head.is_none() {
+        std::collections::binary_heap::PeekMut::pop(first);
+    }
+
+    Ok(record)
+}
+
+// A record in the heap used for top-n sorting, ordered by its position in the output
+struct HeapEntry<'a> {
+    record: Record,
+    ordering: &'a OrderMaker,
+    ascending: bool,
+}
+
+impl Ord for HeapEntry<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let ordering = self.ordering.run(&self.record, &other.record);
+        if self.ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        }
+    }
+}
+
+impl PartialOrd for HeapEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == std::cmp::Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry<'_> {}
+
 impl OrderBy {
     fn next(&mut self) -> Option<Record> {
-        if self.records.is_empty() {
-            self.records = self.producer.by_ref().collect::<Vec<_>>();
+        if self.error.is_some() {
+            return None;
+        }
 
-            if self.ascending {
-                self.records.sort_by(|a, b| self.ordering.run(b, a));
-            } else {
-                self.records.sort_by(|a, b| self.ordering.run(a, b));
+        match self.next_record() {
+            Ok(record) => record,
+            Err(error) => {
+                self.error = Some(error);
+                self.records.clear();
+                self.runs.clear();
+                None
             }
         }
+    }
 
-        self.records.pop()
+    fn next_record(&mut self) -> anyhow::Result<Option<Record>> {
+        if self.runs.is_empty() && self.records.is_empty() {
+            match self.limit {
+                Some(limit) => self.collect_top(limit),
+                None => self.sort_input()?,
+            }
+        }
+
+        if self.runs.is_empty() {
+            Ok(self.records.pop())
+        } else {
+            pop_first(&mut self.runs)
+        }
+    }
+
+    // Compares records by where they go in the output
+    fn output_order(&self, a: &Record, b: &Record) -> std::cmp::Ordering {
+        if self.ascending {
+            self.ordering.run(a, b)
+        } else {
+            self.ordering.run(b, a)
+        }
+    }
+
+    // Sorts the input in memory if it fits in `memory_limit`, otherwise it's split into sorted
+    // runs on disk which get merged back together in `next_record`
+    fn sort_input(&mut self) -> anyhow::Result<()> {
+        let mut used = 0;
+
+        while let Some(record) = self.producer.next() {
+            used += record.get_size();
+            self.records.push(record);
+
+            if used > self.memory_limit {
+                self.spill_records()?;
+                used = 0;
+            }
+        }
+
+        if !self.runs.is_empty() && !self.records.is_empty() {
+            self.spill_records()?;
+        }
+        self.reduce_runs()?;
+
+        // records get popped off the end, so the first in the output has to be last
+        let mut records = std::mem::take(&mut self.records);
+        records.sort_by(|a, b| self.output_order(b, a));
+        self.records = records;
+
+        Ok(())
+    }
+
+    fn spill_records(&mut self) -> anyhow::Result<()> {
+        let mut records = std::mem::take(&mut self.records);
+        records.sort_by(|a, b| self.output_order(a, b));
+
+        let run = SortedRun::spill(
+            records.into_iter(),
+            &self.schema,
+            &self.ordering,
+            self.ascending,
+        )?;
+        self.runs.push(run);
+
+        Ok(())
+    }
+
+    // How many runs get merged at once, every one of them buffers a page
+    fn fan_in(&self) -> usize {
+        (self.memory_limit / PAGE_SIZE).clamp(2, MAX_MERGE_FAN_IN)
+    }
+
+    // Merges runs into longer ones until few enough are left to merge them all at once. The
+    // oldest runs get merged first, so the runs of a pass are about the same length.
+    fn reduce_runs(&mut self) -> anyhow::Result<()> {
+        let fan_in = self.fan_in();
+        if self.runs.len() <= fan_in {
+            return Ok(());
+        }
+
+        let mut runs = std::mem::take(&mut self.runs).into_vec();
+        while runs.len() > fan_in {
+            let merged = runs.drain(..fan_in).collect::<BinaryHeap<_>>();
+            runs.push(SortedRun::merge(merged, &self.schema)?);
+        }
+        self.runs = runs.into();
+
+        Ok(())
+    }
+
+    // Keeps the `limit` records that come first in the output in a max-heap, so only `limit`
+    // records are ever held in memory instead of the whole input
+    fn collect_top(&mut self, limit: usize) {
+        if limit == 0 {
+            return;
+        }
+
+        // a huge LIMIT doesn't mean that many records come in, so the heap grows as they do
+        let mut heap = BinaryHeap::with_capacity(limit.min(1024));
+
+        for record in self.producer.by_ref() {
+            let entry = HeapEntry {
+                record,
+                ordering: &self.ordering,
+                ascending: self.ascending,
+            };
+
+            if heap.len() < limit {
+                heap.push(entry);
+            } else if entry < *heap.peek().unwrap() {
+                heap.pop();
+                heap.push(entry);
+            }
+        }
+
+        // records get popped off the end, so the first in the output has to be last
+        self.records = heap
+            .into_sorted_vec()
+            .into_iter()
+            .rev()
+            .map(|entry| entry.record)
+            .collect();
+    }
+}
+
+pub struct Limit {
+    pub limit: usize,
+    pub offset: usize,
+
+    // how many records have been pulled from the producer so far
+    pub seen: usize,
+
+    pub producer: Box<RelOp>,
+}
+
+impl Limit {
+    fn next(&mut self) -> Option<Record> {
+        while self.seen < self.offset {
+            self.producer.next()?;
+            self.seen += 1;
+        }
+
+        if self.seen >= self.offset.saturating_add(self.limit) {
+            return None;
+        }
+
+        self.seen += 1;
+        self.producer.next()
     }
 }
 
@@ -520,3 +1471,435 @@ impl WriteOut {
         None
     }
 }
+
+// Wraps an operator for EXPLAIN with what the planner expects of it and, once it runs, what it
+// actually did
+pub struct Instrument {
+    pub estimate: Estimate,
+    pub stats: ExecutionStats,
+    pub producer: Box<RelOp>,
+}
+
+pub struct Estimate {
+    pub rows: u64,
+    // including the cost of everything below the operator
+    pub cost: f64,
+}
+
+#[derive(Default)]
+pub struct ExecutionStats {
+    pub started: bool,
+    pub rows: u64,
+    // spent in the operator and everything below it
+    pub time: std::time::Duration,
+    // the most bytes of records the operator held in memory at once, not counting its producers
+    pub peak_memory: usize,
+}
+
+impl Instrument {
+    fn next(&mut self) -> Option<Record> {
+        self.stats.started = true;
+
+        let start = std::time::Instant::now();
+        let record = self.producer.next();
+        self.stats.time += start.elapsed();
+
+        if record.is_some() {
+            self.stats.rows += 1;
+        }
+
+        // adding up the memory after every record would take time quadratic in what the operator
+        // holds, so it's only checked at powers of two and at the end
+        if record.is_none() || self.stats.rows.is_power_of_two() {
+            let memory = self.producer.memory_usage();
+            self.stats.peak_memory = self.stats.peak_memory.max(memory);
+        }
+
+        record
+    }
+
+    fn annotation(&self) -> String {
+        let estimate = format!(
+            "(rows={} cost={:.2})",
+            self.estimate.rows, self.estimate.cost
+        );
+        if !self.stats.started {
+            return estimate;
+        }
+
+        format!(
+            "{estimate} (actual rows={} time={:.3}ms memory={}B)",
+            self.stats.rows,
+            self.stats.time.as_secs_f64() * 1000.0,
+            self.stats.peak_memory
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    fn create_schema(atts: &[&str]) -> Schema {
+        let names = atts.iter().map(|att| att.to_string()).collect::<Vec<_>>();
+        let types = vec!["INTEGER".to_string(); atts.len()];
+        let distincts = vec![0; atts.len()];
+        Schema::from_attributes(&names, &types, &distincts)
+    }
+
+    // The returned temp files have to outlive the scan
+    fn create_scan(schema: &Schema, rows: &[&str]) -> (NamedTempFile, NamedTempFile, RelOp) {
+        let mut text_file = NamedTempFile::new().unwrap();
+        for row in rows {
+            writeln!(text_file, "{row}").unwrap();
+        }
+        text_file.flush().unwrap();
+
+        let db_path = NamedTempFile::new().unwrap();
+        let mut file = DBFile::new();
+        file.create(db_path.path(), FileType::Heap).unwrap();
+        file.load(schema, text_file.path().to_str().unwrap())
+            .unwrap();
+        file.move_first();
+
+        (text_file, db_path, RelOp::Scan(Scan { file }))
+    }
+
+    // joins `l(a, b)` with `r(c, d)` on `a = c`
+    fn left_and_right() -> (Vec<NamedTempFile>, RelOp, RelOp, Cnf) {
+        let left_schema = create_schema(&["a", "b"]);
+        let right_schema = create_schema(&["c", "d"]);
+
+        let (left_text, left_db, left) = create_scan(&left_schema, &["1|10|", "2|20|", "3|30|"]);
+        let (right_text, right_db, right) =
+            create_scan(&right_schema, &["1|100|", "1|101|", "4|400|"]);
+
+        let predicate: Cnf = Comparison {
+            operand1: Target::Left,
+            which_att1: 0,
+            operand2: Target::Right,
+            which_att2: 0,
+            att_type: Type::Integer,
+            op: CompOp::Equal,
+        }
+        .into();
+
+        (
+            vec![left_text, left_db, right_text, right_db],
+            left,
+            right,
+            predicate,
+        )
+    }
+
+    fn collect_sorted(relop: impl Iterator<Item = Record>) -> Vec<String> {
+        let mut rows = relop
+            .map(|record| {
+                String::from_utf8(record.to_bytes())
+                    .unwrap()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>();
+        rows.sort();
+        rows
+    }
+
+    fn expected(join_type: JoinType) -> Vec<&'static str> {
+        let mut rows = vec!["1|10|1|100|", "1|10|1|101|"];
+        if join_type.preserves_left() {
+            rows.extend(["2|20|||", "3|30|||"]);
+        }
+        if join_type.preserves_right() {
+            rows.push("||4|400|");
+        }
+        rows.sort();
+        rows
+    }
+
+    const JOIN_TYPES: [JoinType; 4] = [
+        JoinType::Inner,
+        JoinType::LeftOuter,
+        JoinType::RightOuter,
+        JoinType::FullOuter,
+    ];
+
+    #[test]
+    fn test_nested_loop_join_types() {
+        for join_type in JOIN_TYPES {
+            let (_files, left, right, predicate) = left_and_right();
+            let join = RelOp::NestedLoopJoin(NestedLoopJoin {
+                predicate,
+                join_type,
+                left_atts: 2,
+                right_atts: 2,
+                records: Vec::new(),
+                left_producer: Box::new(left),
+                right_producer: Box::new(right),
+            });
+
+            assert_eq!(collect_sorted(join), expected(join_type), "{join_type:?}");
+        }
+    }
+
+    fn hash_join(
+        join_type: JoinType,
+        fill_left: bool,
+        memory_limit: usize,
+        predicate: Cnf,
+        left: RelOp,
+        right: RelOp,
+    ) -> RelOp {
+        let (left_projection, right_projection) = predicate.get_projections();
+        RelOp::HashJoin(HashJoin {
+            predicate,
+            join_type,
+            fill_left,
+            left_atts: 2,
+            right_atts: 2,
+            hash_table: HashMap::new(),
+            filled: false,
+            buf: Vec::new(),
+            left_projection,
+            right_projection,
+            memory_limit,
+            left_schema: create_schema(&["a", "b"]),
+            right_schema: create_schema(&["c", "d"]),
+            partitions: Vec::new(),
+            probe_partition: None,
+            block_join: None,
+            error: None,
+            left_producer: Box::new(left),
+            right_producer: Box::new(right),
+        })
+    }
+
+    #[test]
+    fn test_hash_join_types() {
+        // a memory limit of 0 forces the join to go through partitions on disk
+        for memory_limit in [MEMORY_LIMIT, 0] {
+            for fill_left in [true, false] {
+                for join_type in JOIN_TYPES {
+                    let (_files, left, right, predicate) = left_and_right();
+                    let join =
+                        hash_join(join_type, fill_left, memory_limit, predicate, left, right);
+
+                    assert_eq!(
+                        collect_sorted(join),
+                        expected(join_type),
+                        "{join_type:?} fill_left={fill_left} memory_limit={memory_limit}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_grace_hash_join() {
+        let left_rows = (0..300)
+            .map(|i| format!("{}|{}|", i % 100, i))
+            .collect::<Vec<_>>();
+        let right_rows = (50..150)
+            .map(|i| format!("{i}|{}|", i * 10))
+            .collect::<Vec<_>>();
+
+        let join_with_limit = |memory_limit| {
+            let (_left_text, _left_db, left) = create_scan(
+                &create_schema(&["a", "b"]),
+                &left_rows.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+            let (_right_text, _right_db, right) = create_scan(
+                &create_schema(&["c", "d"]),
+                &right_rows.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+            let (_, _, _, predicate) = left_and_right();
+
+            collect_sorted(hash_join(
+                JoinType::FullOuter,
+                true,
+                memory_limit,
+                predicate,
+                left,
+                right,
+            ))
+        };
+
+        let in_memory = join_with_limit(MEMORY_LIMIT);
+        // 150 matches, 150 unmatched left records and 50 unmatched right records
+        assert_eq!(in_memory.len(), 350);
+        assert_eq!(join_with_limit(2048), in_memory);
+        assert_eq!(join_with_limit(0), in_memory);
+    }
+
+    #[test]
+    fn test_hash_join_skewed_key() {
+        // most of the left records have the same key, so no partitioning gets them under the
+        // memory limit and they have to be joined a block at a time
+        let left_rows = (0..400)
+            .map(|i| format!("{}|{}|", if i % 4 == 0 { i } else { 7 }, i))
+            .collect::<Vec<_>>();
+        let right_rows = (0..60)
+            .map(|i| format!("{}|{}|", if i % 10 == 0 { 7 } else { i * 4 }, i))
+            .collect::<Vec<_>>();
+
+        let join_with_limit = |join_type, fill_left, memory_limit| {
+            let (_left_text, _left_db, left) = create_scan(
+                &create_schema(&["a", "b"]),
+                &left_rows.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+            let (_right_text, _right_db, right) = create_scan(
+                &create_schema(&["c", "d"]),
+                &right_rows.iter().map(String::as_str).collect::<Vec<_>>(),
+            );
+            let (_, _, _, predicate) = left_and_right();
+
+            let mut join = hash_join(join_type, fill_left, memory_limit, predicate, left, right);
+            let rows = collect_sorted(&mut join);
+            assert!(join.take_error().is_none());
+            rows
+        };
+
+        for join_type in JOIN_TYPES {
+            for fill_left in [true, false] {
+                assert_eq!(
+                    join_with_limit(join_type, fill_left, 2048),
+                    join_with_limit(join_type, fill_left, MEMORY_LIMIT),
+                    "{join_type:?} fill_left={fill_left}"
+                );
+            }
+        }
+
+        // the 300 left records with key 7 match all 6 right ones, of the other 100 left records 54
+        // find a match
+        let full_outer = join_with_limit(JoinType::FullOuter, true, 0);
+        assert_eq!(full_outer.len(), 300 * 6 + 54 + 46);
+    }
+
+    #[test]
+    fn test_merge_join_types() {
+        for join_type in JOIN_TYPES {
+            let (_files, left, right, predicate) = left_and_right();
+            let (left_ordering, right_ordering) = predicate.get_sort_orders();
+            let join = RelOp::MergeJoin(MergeJoin {
+                buf: Vec::new(),
+                predicate,
+                join_type,
+                left_atts: 2,
+                right_atts: 2,
+                left_ordering,
+                right_ordering,
+                started: false,
+                left_record: None,
+                right_record: None,
+                left_producer: Box::new(left),
+                right_producer: Box::new(right),
+            });
+
+            assert_eq!(collect_sorted(join), expected(join_type), "{join_type:?}");
+        }
+    }
+
+    fn sort_by_a(rows: &[String], ascending: bool, memory_limit: usize) -> Vec<String> {
+        let schema = create_schema(&["a", "b"]);
+        let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+        let (_text, _db, scan) = create_scan(&schema, &rows);
+
+        let mut order_by = OrderBy {
+            ordering: OrderMaker::from_atts(&schema, &[0]),
+            records: Vec::new(),
+            producer: Box::new(scan),
+            ascending,
+            limit: None,
+            schema,
+            memory_limit,
+            runs: BinaryHeap::new(),
+            error: None,
+        };
+
+        let mut sorted = Vec::new();
+        while let Some(record) = order_by.next() {
+            // with no memory at all every record starts out in its own run, which takes a few
+            // passes to merge
+            assert!(order_by.runs.len() <= order_by.fan_in());
+            sorted.push(
+                String::from_utf8(record.to_bytes())
+                    .unwrap()
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+        assert!(order_by.runs.is_empty());
+        assert!(order_by.error.is_none());
+
+        sorted
+    }
+
+    #[test]
+    fn test_external_sort() {
+        let mut rows = (0..50)
+            .map(|i| format!("{}|{}|", (i * 7) % 50, i))
+            .collect::<Vec<_>>();
+        rows.push("|50|".to_string());
+
+        for ascending in [true, false] {
+            let in_memory = sort_by_a(&rows, ascending, MEMORY_LIMIT);
+            let external = sort_by_a(&rows, ascending, 0);
+            let few_runs = sort_by_a(&rows, ascending, 1000);
+
+            let mut expected = (0..50)
+                .map(|a| format!("{}|{}|", a, (a * 43) % 50))
+                .collect::<Vec<_>>();
+            // nulls come first
+            expected.insert(0, "|50|".to_string());
+            if !ascending {
+                expected.reverse();
+            }
+
+            assert_eq!(in_memory, expected);
+            assert_eq!(external, expected);
+            assert_eq!(few_runs, expected);
+        }
+    }
+
+    #[test]
+    fn test_external_sort_read_error() {
+        let schema = create_schema(&["a", "b"]);
+        let rows = (0..20_000)
+            .map(|i| format!("{}|{}|", (i * 7) % 20_000, i))
+            .collect::<Vec<_>>();
+        let rows = rows.iter().map(String::as_str).collect::<Vec<_>>();
+        let (_text, _db, scan) = create_scan(&schema, &rows);
+
+        let mut order_by = OrderBy {
+            ordering: OrderMaker::from_atts(&schema, &[0]),
+            records: Vec::new(),
+            producer: Box::new(scan),
+            ascending: true,
+            limit: None,
+            schema,
+            memory_limit: 2 * PAGE_SIZE,
+            runs: BinaryHeap::new(),
+            error: None,
+        };
+        assert!(order_by.next().is_some());
+        assert!(order_by.runs.len() > 1);
+
+        // the runs span several pages, the ones after the first can't be read anymore
+        for run in order_by.runs.iter() {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(&run.file.path)
+                .unwrap();
+            file.set_len(0).unwrap();
+        }
+
+        let mut sorted = 1;
+        while order_by.next().is_some() {
+            sorted += 1;
+        }
+        assert!(sorted < rows.len());
+        assert!(RelOp::OrderBy(order_by).take_error().is_some());
+    }
+}
@@ -7,6 +7,51 @@ pub struct Attribute {
     pub name: String,
     pub type_: Type,
     pub no_distinct: u64,
+    // only known for numeric attributes, and only once they've been analyzed
+    pub statistics: Option<Statistics>,
+}
+
+// The range of values of a numeric attribute. The histogram is equi-depth: its bounds go from min
+// to max and split the values into buckets that each hold the same number of records. Without a
+// histogram the values are assumed to be spread evenly between min and max.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub min: f64,
+    pub max: f64,
+    pub histogram: Vec<f64>,
+}
+
+impl Statistics {
+    // Estimated fraction of the values that are less than `value`
+    pub fn fraction_below(&self, value: f64) -> f64 {
+        if value <= self.min {
+            return 0.0;
+        }
+        if value > self.max {
+            return 1.0;
+        }
+
+        let bounds = if self.histogram.len() >= 2 {
+            self.histogram.as_slice()
+        } else {
+            &[self.min, self.max]
+        };
+        let buckets = (bounds.len() - 1) as f64;
+
+        // the buckets below the one `value` falls in, and a part of that one
+        let bucket = bounds
+            .partition_point(|&bound| bound < value)
+            .clamp(1, bounds.len() - 1)
+            - 1;
+        let (low, high) = (bounds[bucket], bounds[bucket + 1]);
+        let within = if high > low {
+            ((value - low) / (high - low)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+
+        (bucket as f64 + within) / buckets
+    }
 }
 
 // This handles Attribue::Attribute()
@@ -16,6 +61,7 @@ impl Default for Attribute {
             name: String::new(),
             type_: Type::Name,
             no_distinct: 0,
+            statistics: None,
         }
     }
 }
@@ -53,6 +99,7 @@ impl Schema {
                     name: attr.clone(),
                     type_,
                     no_distinct: *no_distinct,
+                    statistics: None,
                 }
             })
             .collect::<Vec<_>>();
@@ -147,6 +194,31 @@ impl Schema {
         self.no_tuples = no_tuples as u64;
     }
 
+    // Same estimate as `join_right`, but for joins on attributes that don't share a name, given
+    // as pairs of indices into `self` and `other`. All of the attributes of `other` are kept.
+    pub fn join_on(&mut self, other: &Schema, join_attributes: &[(usize, usize)]) {
+        let mut no_tuples = self.no_tuples as f64 * other.no_tuples as f64;
+        let mut attributes = other.attributes.clone();
+
+        for &(index, other_index) in join_attributes {
+            let self_distincts = self.attributes[index].no_distinct;
+            let other_distincts = other.attributes[other_index].no_distinct;
+
+            let max_distincts = u64::max(self_distincts, other_distincts) as f64;
+            if max_distincts != 0.0 {
+                no_tuples /= max_distincts;
+            }
+
+            // only values on both sides survive the join
+            let min_distincts = u64::min(self_distincts, other_distincts);
+            self.attributes[index].no_distinct = min_distincts;
+            attributes[other_index].no_distinct = min_distincts;
+        }
+
+        self.attributes.extend(attributes);
+        self.no_tuples = no_tuples as u64;
+    }
+
     pub fn index_of(&self, attribute: &str) -> Option<usize> {
         self.attributes
             .iter()
@@ -171,6 +243,20 @@ impl Schema {
             .is_some()
     }
 
+    pub fn get_statistics(&self, attribute: &str) -> Option<&Statistics> {
+        self.attributes[self.index_of(attribute)?]
+            .statistics
+            .as_ref()
+    }
+
+    pub fn set_statistics(&mut self, attribute: &str, statistics: Option<Statistics>) -> bool {
+        self.index_of(attribute)
+            .map(|index| {
+                self.attributes[index].statistics = statistics;
+            })
+            .is_some()
+    }
+
     pub fn rename_att(&mut self, old_name: &str, new_name: &str) -> bool {
         if self.index_of(new_name).is_some() {
             return false;
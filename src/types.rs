@@ -50,6 +50,18 @@ impl CompOp {
         }
     }
 
+    // The operator that gives the same result with the operands swapped
+    pub fn reversed(&self) -> Self {
+        match self {
+            CompOp::Less => CompOp::Greater,
+            CompOp::LessEqual => CompOp::GreaterEqual,
+            CompOp::Greater => CompOp::Less,
+            CompOp::GreaterEqual => CompOp::LessEqual,
+            CompOp::Equal => CompOp::Equal,
+            CompOp::NotEqual => CompOp::NotEqual,
+        }
+    }
+
     pub fn to_normal_form(&self) -> Self {
         match self {
             CompOp::Less => CompOp::Less,
@@ -69,3 +81,24 @@ pub enum FileType {
     Sorted,
     Index,
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum JoinType {
+    Inner,
+    LeftOuter,
+    RightOuter,
+    FullOuter,
+}
+
+impl JoinType {
+    // whether unmatched records from the left input are kept (padded with nulls)
+    pub fn preserves_left(&self) -> bool {
+        matches!(self, JoinType::LeftOuter | JoinType::FullOuter)
+    }
+
+    // whether unmatched records from the right input are kept (padded with nulls)
+    pub fn preserves_right(&self) -> bool {
+        matches!(self, JoinType::RightOuter | JoinType::FullOuter)
+    }
+}